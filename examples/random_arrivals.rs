@@ -13,7 +13,7 @@ pub fn main() {
     let mut controller = Controller::new();
 
     // Establish the async tasks to repeatedly send orders over tcp
-    let tcp_address = format!("127.0.0.1:5000");
+    let tcp_address = String::from("127.0.0.1:5000");
 
     let tcp_arrivals = RandBehavior::tcp_arrival_interval(Arc::clone(&traders), 500, tcp_address.clone()); 
     let tcp_updates = RandBehavior::tcp_update_interval(Arc::clone(&traders), 1000, tcp_address.clone());
@@ -28,9 +28,9 @@ pub fn main() {
     env_logger::init();
     let ws_address: &'static str = "ws://127.0.0.1:3015";
 
-    let ws_arrivals = RandBehavior::ws_arrival_interval(Arc::clone(&traders), 500, &ws_address); 
-    let ws_updates = RandBehavior::ws_update_interval(Arc::clone(&traders), 1000, &ws_address);
-    let ws_cancels = RandBehavior::ws_cancel_interval(Arc::clone(&traders), 2000, &ws_address);
+    let ws_arrivals = RandBehavior::ws_arrival_interval(Arc::clone(&traders), 500, ws_address); 
+    let ws_updates = RandBehavior::ws_update_interval(Arc::clone(&traders), 1000, ws_address);
+    let ws_cancels = RandBehavior::ws_cancel_interval(Arc::clone(&traders), 2000, ws_address);
 
     controller.push(ws_arrivals);
     controller.push(ws_updates);
@@ -6,18 +6,17 @@ use futures::{Future};
 
 use futures::future;
 
-#[derive(Debug)]
-pub enum State {
-	Process,
-	PreAuction,
-	Auction,
-}
-
 // A wrapper around tokio to dispatch tasks asynchronously
 pub struct Controller {
 	tasks: Vec<AsyncTask>,
 }
 
+impl Default for Controller {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl Controller {
 	pub fn new() -> Controller {
 		Controller{
@@ -36,7 +35,7 @@ impl Controller {
 	}
 }
 
-pub type AsyncTask = Box<Future<Item = (), Error = ()> + Send>;
+pub type AsyncTask = Box<dyn Future<Item = (), Error = ()> + Send>;
 
 // A wrapper to easily create dispatch closure's asynchronously as tasks in tokio
 pub struct Task {
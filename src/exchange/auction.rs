@@ -1,7 +1,16 @@
-use crate::controller::{Task, State};
-use crate::exchange::order_book::Book;
+use crate::controller::Task;
+use crate::exchange::order_book::{Book, BookSnapshot};
+use crate::exchange::settlement::Ledger;
+use crate::exchange::events::EventQueue;
+use crate::exchange::config::ExchangeConfig;
+use crate::exchange::history::AuctionHistory;
+use crate::exchange::scheduling::{Scheduler, POLL_INTERVAL};
+use crate::exchange::self_trade::{self, SelfTradeGuard, SelfTradePrevention, SelfTradeReport};
+use crate::order::{Order, TradeType};
+use crate::order::schedule::Schedule;
 
 use std::sync::{Mutex, Arc};
+use std::time::Duration;
 
 use rayon::prelude::*;
 use crate::utility::get_time;
@@ -9,56 +18,573 @@ use crate::utility::get_time;
 
 const EPSILON: f64 =  0.000_000_001;
 
+/// The algorithm used to find the market clearing price.
+/// BinarySearch -> bisects the price bounds until aggregate supply and demand are within EPSILON
+/// Exact -> solves the linear segment between order breakpoints where supply and demand cross,
+/// falling back to BinarySearch for schedules that aren't piecewise linear
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClearingMethod {
+	BinarySearch,
+	Exact,
+}
+
+/// The rule used to pick the clearing price when aggregate supply and demand
+/// are equal over an interval of prices rather than crossing at a single point.
+/// Midpoint -> the center of the interval
+/// Lowest -> the lower edge of the interval
+/// Highest -> the upper edge of the interval
+/// ClosestToPrevious -> the price in the interval closest to the last clearing price
+/// MaxVolume -> the price in the interval that trades the most volume
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TieBreak {
+	Midpoint,
+	Lowest,
+	Highest,
+	ClosestToPrevious,
+	MaxVolume,
+}
+
+/// Why an auction did or did not find a clearing price, along with the numbers
+/// needed to diagnose it.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClearingOutcome {
+	/// The bids Book has no orders
+	EmptyBids { num_asks: usize },
+	/// The asks Book has no orders
+	EmptyAsks { num_bids: usize },
+	/// The highest bid p_high is below the lowest ask p_low so no prices overlap
+	NoOverlap { best_bid: f64, best_ask: f64 },
+	/// Aggregate supply and demand cross at a unique price
+	Cross { price: f64, demand: f64, supply: f64, iterations: usize },
+	/// Aggregate supply and demand are equal over [low, high], price is the point chosen
+	Interval { low: f64, high: f64, price: f64, volume: f64 },
+	/// The search stopped without finding supply equal to demand
+	NoConvergence { last_price: f64, demand: f64, supply: f64, iterations: usize },
+}
+
+impl ClearingOutcome {
+	/// The clearing price if the auction found one
+	pub fn price(&self) -> Option<f64> {
+		match self {
+			ClearingOutcome::Cross { price, .. } => Some(*price),
+			ClearingOutcome::Interval { price, .. } => Some(*price),
+			_ => None,
+		}
+	}
+
+	/// True when the books simply had nothing to trade, as opposed to the
+	/// engine failing to find a price
+	pub fn is_no_trade(&self) -> bool {
+		matches!(self, ClearingOutcome::EmptyBids { .. } |
+			ClearingOutcome::EmptyAsks { .. } |
+			ClearingOutcome::NoOverlap { .. })
+	}
+
+	/// A short name of the outcome for logging and monitoring
+	pub fn kind(&self) -> &'static str {
+		match self {
+			ClearingOutcome::EmptyBids { .. } => "empty_bids",
+			ClearingOutcome::EmptyAsks { .. } => "empty_asks",
+			ClearingOutcome::NoOverlap { .. } => "no_overlap",
+			ClearingOutcome::Cross { .. } => "cross",
+			ClearingOutcome::Interval { .. } => "interval",
+			ClearingOutcome::NoConvergence { .. } => "no_convergence",
+		}
+	}
+}
+
+/// Where the aggregate curves are evaluated.
+/// Grid -> 'points' evenly spaced prices across the range, including both ends
+/// Breakpoints -> every order's p_low, p_high and schedule breakpoints in the range
+/// along with its ends, which is exact for piecewise linear orders
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum CurveSampling {
+	Grid { points: usize },
+	Breakpoints,
+}
+
+/// Options for computing the aggregate supply and demand curves.
+/// sampling: CurveSampling -> the resolution of the curves
+/// range: Option<(f64, f64)> -> the prices to cover, None uses the books' price bounds
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CurveOptions {
+	pub sampling: CurveSampling,
+	pub range: Option<(f64, f64)>,
+}
+
+impl Default for CurveOptions {
+	fn default() -> Self {
+		CurveOptions {
+			sampling: CurveSampling::Grid { points: 100 },
+			range: None,
+		}
+	}
+}
+
+/// The aggregate demand and supply at a single price
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct CurvePoint {
+	pub price: f64,
+	pub demand: f64,
+	pub supply: f64,
+}
+
+/// The aggregate supply and demand curves of the market, ie for a depth of market feed.
+/// bids_version: u64 -> version of the bids Book the curves were computed from
+/// asks_version: u64 -> version of the asks Book the curves were computed from
+/// points: Vec<CurvePoint> -> the curves sorted by price, empty if both books are
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MarketCurves {
+	pub bids_version: u64,
+	pub asks_version: u64,
+	pub points: Vec<CurvePoint>,
+}
+
+/// The rate a single order executes at for the clearing price of an auction.
+/// trader_id: String -> identifier of the trader that owns the order
+/// order_id: Option<u64> -> exchange assigned identifier of the filled order
+/// seq: Option<u64> -> sequence number the order was stamped with on arrival
+/// received: Option<Duration> -> time the exchange received the order
+/// trade_type: TradeType{Bid, Ask} -> which book the order was filled from
+/// rate: f64 -> the order's schedule evaluated at the clearing price
+#[derive(Debug, Clone, PartialEq)]
+pub struct Fill {
+	pub trader_id: String,
+	pub order_id: Option<u64>,
+	pub seq: Option<u64>,
+	pub received: Option<Duration>,
+	pub trade_type: TradeType,
+	pub rate: f64,
+}
+
+#[cfg(test)]
+impl Fill {
+	/// A fill of 'rate' for an order without an order_id or arrival stamps
+	pub(crate) fn of(trader_id: &str, trade_type: TradeType, rate: f64) -> Fill {
+		Fill { trader_id: String::from(trader_id), order_id: None, seq: None, received: None, trade_type, rate }
+	}
+}
+
+/// The outcome of a single batch auction.
+/// outcome: ClearingOutcome -> how the clearing price was or wasn't found
+/// tie_break: TieBreak -> the rule used to pick the price if the outcome was an interval
+/// clearing_price: Option<f64> -> market clearing price, None if no cross was found
+/// bid_fills: Vec<Fill> -> executed rate of every order in the bids Book
+/// ask_fills: Vec<Fill> -> executed rate of every order in the asks Book
+/// demand: f64 -> aggregate demand at the clearing price
+/// supply: f64 -> aggregate supply at the clearing price
+/// volume: f64 -> total rate traded, the smaller of demand and supply
+/// imbalance: f64 -> residual demand minus supply left unfilled at the clearing price
+/// self_trade: SelfTradeReport -> orders acted on to prevent self trades and the volume prevented
+#[derive(Debug, Clone)]
+pub struct AuctionResult {
+	pub outcome: ClearingOutcome,
+	pub tie_break: TieBreak,
+	pub clearing_price: Option<f64>,
+	pub bid_fills: Vec<Fill>,
+	pub ask_fills: Vec<Fill>,
+	pub demand: f64,
+	pub supply: f64,
+	pub volume: f64,
+	pub imbalance: f64,
+	pub self_trade: SelfTradeReport,
+}
+
+impl AuctionResult {
+	/// Rations the fills of the long side pro rata so that the total rate bought
+	/// equals the total rate sold, then updates the traded volume. Needed when the
+	/// clearing price isn't an exact cross, ie after snapping to a tick, or when
+	/// fills have been capped after the auction.
+	pub fn ration(&mut self) {
+		let bought: f64 = self.bid_fills.iter().map(|f| f.rate).sum();
+		let sold: f64 = self.ask_fills.iter().map(|f| f.rate).sum();
+		let volume = bought.min(sold);
+
+		let long_side = if bought > sold { 
+			Some((&mut self.bid_fills, volume / bought)) 
+		} else if sold > bought { 
+			Some((&mut self.ask_fills, volume / sold)) 
+		} else { 
+			None 
+		};
+
+		if let Some((fills, ratio)) = long_side {
+			for fill in fills.iter_mut() {
+				fill.rate *= ratio;
+			}
+		}
+		self.volume = volume;
+	}
+
+	/// An auction result where no trading occurred
+	pub fn no_cross(outcome: ClearingOutcome) -> AuctionResult {
+		AuctionResult {
+			outcome,
+			tie_break: TieBreak::Midpoint,
+			clearing_price: None,
+			bid_fills: Vec::new(),
+			ask_fills: Vec::new(),
+			demand: 0.0,
+			supply: 0.0,
+			volume: 0.0,
+			imbalance: 0.0,
+			self_trade: SelfTradeReport::default(),
+		}
+	}
+}
+
 pub struct Auction {}
 
 impl Auction {
-	// Iterate over each order in parallel and compute
-	// the closure for each. 
-	pub fn calc_aggs(p: f64, bids: Arc<Book>, asks: Arc<Book>) -> (f64, f64) {
-		let bids = bids.orders.lock().expect("ERROR: No bids book");
-		let asks = asks.orders.lock().expect("ERROR: No asks book");
-
-		let agg_demand: f64 = bids.par_iter()
-		    .map(|order| {
-		    	if p <= order.p_low {
-		    		order.u_max
-		    	} else if p > order.p_high {
-		    		0.0
-		    	} else {
-		    		order.calculate(p)
-		    	}
-		    }).sum();
-
-		let agg_supply: f64 = asks.par_iter()
-		    .map(|order| {
-		    	if p < order.p_low {
-		    		0.0
-		    	} else if p >= order.p_high {
-		    		order.u_max
-		    	} else {
-		    		order.calculate(p)
-		    	}
-		    }).sum();
+	// Iterate over each order in parallel and evaluate the schedule for each. Reads
+	// snapshots of the books so writers aren't blocked, see Book::snapshot.
+	pub fn calc_aggs(p: f64, bids: &BookSnapshot, asks: &BookSnapshot) -> (f64, f64) {
+		Auction::aggs_of(p, &bids.orders, &asks.orders)
+	}
+
+	/// Computes the aggregate demand and supply curves of the books over a range of
+	/// prices, sampled on a grid or at the orders' breakpoints. Both curves are read
+	/// from the same snapshot of each book.
+	pub fn calc_curves(bids: Arc<Book>, asks: Arc<Book>, options: &CurveOptions) -> MarketCurves {
+		let bids = bids.snapshot();
+		let asks = asks.snapshot();
+		let mut curves = MarketCurves { bids_version: bids.version, asks_version: asks.version, points: Vec::new() };
+
+		let bounds = [bids.min_price, asks.min_price, bids.max_price, asks.max_price];
+		let (low, high) = match options.range {
+			Some(range) => range,
+			None => {
+				let bounds: Vec<f64> = bounds.iter().flatten().copied().collect();
+				if bounds.is_empty() {
+					return curves;
+				}
+				(bounds.iter().copied().fold(f64::MAX, f64::min), bounds.iter().copied().fold(f64::MIN, f64::max))
+			},
+		};
+		if !low.is_finite() || !high.is_finite() || low > high {
+			return curves;
+		}
+
+		let prices: Vec<f64> = match options.sampling {
+			CurveSampling::Grid { points: 0 } => Vec::new(),
+			CurveSampling::Grid { points: 1 } => vec![low],
+			CurveSampling::Grid { points } => (0..points)
+				.map(|i| low + (high - low) * i as f64 / (points - 1) as f64)
+				.collect(),
+			CurveSampling::Breakpoints => Auction::breakpoints_between(low, high, &bids, &asks),
+		};
+
+		curves.points = prices.into_iter()
+			.map(|price| {
+				let (demand, supply) = Auction::aggs_of(price, &bids.orders, &asks.orders);
+				CurvePoint { price, demand, supply }
+			})
+			.collect();
+		curves
+	}
+
+	/// Every order's p_low, p_high and schedule breakpoints strictly between low and high,
+	/// along with low and high themselves, sorted and without duplicates
+	fn breakpoints_between(low: f64, high: f64, bids: &BookSnapshot, asks: &BookSnapshot) -> Vec<f64> {
+		let mut prices: Vec<f64> = bids.orders.iter().chain(asks.orders.iter())
+			.flat_map(|o| o.schedule.breakpoints().unwrap_or_default().into_iter().chain(vec![o.p_low, o.p_high]))
+			.filter(|p| *p > low && *p < high)
+			.chain(vec![low, high])
+			.collect();
+		prices.sort_by(|a, b| a.total_cmp(b));
+		prices.dedup();
+		prices
+	}
+
+	/// Computes the aggregate demand and supply at price p of any demand and 
+	/// supply schedules, ie slices of orders or a Book's OrderIndex
+	pub fn aggs_of<'a, S, B, A>(p: f64, bids: B, asks: A) -> (f64, f64)
+	where S: Schedule + 'a, B: IntoParallelIterator<Item = &'a S>, A: IntoParallelIterator<Item = &'a S>
+	{
+		let agg_demand: f64 = bids.into_par_iter()
+		    .map(|schedule| schedule.rate(p))
+		    .sum();
+
+		let agg_supply: f64 = asks.into_par_iter()
+		    .map(|schedule| schedule.rate(p))
+		    .sum();
 
 		(agg_demand, agg_supply)
 	}
 
+
+	/// Evaluates every order in both books at the clearing price to find the rate
+	/// each trader executes at, along with the aggregate volume and imbalance.
+	pub fn allocate_fills(p: f64, bids: &BookSnapshot, asks: &BookSnapshot) -> AuctionResult {
+		let to_fill = |order: &Order| Fill {
+			trader_id: order.trader_id.clone(),
+			order_id: order.order_id,
+			seq: order.seq,
+			received: order.received,
+			trade_type: order.trade_type.clone(),
+			rate: order.rate(p),
+		};
+
+		let bid_fills: Vec<Fill> = bids.orders.par_iter().map(to_fill).collect();
+		let ask_fills: Vec<Fill> = asks.orders.par_iter().map(to_fill).collect();
+
+		let demand: f64 = bid_fills.iter().map(|f| f.rate).sum();
+		let supply: f64 = ask_fills.iter().map(|f| f.rate).sum();
+
+		AuctionResult {
+			outcome: ClearingOutcome::Cross { price: p, demand, supply, iterations: 0 },
+			tie_break: TieBreak::Midpoint,
+			clearing_price: Some(p),
+			bid_fills,
+			ask_fills,
+			demand,
+			supply,
+			volume: demand.min(supply),
+			imbalance: demand - supply,
+			self_trade: SelfTradeReport::default(),
+		}
+	}
+
+	/// Runs a full auction: finds the clearing price of the books with the exchange's
+	/// clearing method, picks a price with its tie break rule if supply and demand are
+	/// equal over an interval, and snaps it to the exchange's tick size. The executed rate 
+	/// of every order is allocated at that price, rationing the long side pro rata.
+	/// 'previous' is the last clearing price, used by TieBreak::ClosestToPrevious.
+	/// Every step of the auction reads the same snapshots of the books.
+	pub fn run_auction(bids: &BookSnapshot, asks: &BookSnapshot, config: &ExchangeConfig, previous: Option<f64>) -> AuctionResult {
+		let mut outcome = match config.clearing_method {
+			ClearingMethod::BinarySearch => Auction::bs_cross(bids, asks),
+			ClearingMethod::Exact => Auction::exact_cross(bids, asks),
+		};
+
+		if let ClearingOutcome::Interval { low, high, .. } = outcome {
+			let price = Auction::break_tie(low, high, config.tie_break, previous, bids, asks);
+			let (dem, sup) = Auction::calc_aggs(price, bids, asks);
+			outcome = ClearingOutcome::Interval { low, high, price, volume: dem.min(sup) };
+		}
+
+		let mut result = match outcome.price() {
+			Some(p) => {
+				let mut result = Auction::allocate_fills(config.snap_price(p), bids, asks);
+				result.outcome = outcome;
+				// Each trader's own buying and selling is netted out before rationing
+				if config.self_trade == SelfTradePrevention::Net {
+					result.self_trade = self_trade::net_fills(&mut result.bid_fills, &mut result.ask_fills);
+					// The aggregates are what is left to trade once traders are netted
+					result.demand = result.bid_fills.iter().map(|f| f.rate).sum();
+					result.supply = result.ask_fills.iter().map(|f| f.rate).sum();
+					result.imbalance = result.demand - result.supply;
+				}
+				result.ration();
+				result
+			},
+			None => AuctionResult::no_cross(outcome),
+		};
+		result.tie_break = config.tie_break;
+		result
+	}
+
+	/// Picks the clearing price within the interval [low, high] where aggregate supply
+	/// and demand are equal according to the supplied rule.
+	pub fn break_tie(low: f64, high: f64, rule: TieBreak, previous: Option<f64>, 
+					 bids: &BookSnapshot, asks: &BookSnapshot) -> f64 {
+		let mid = (low + high) / 2.0;
+		match rule {
+			TieBreak::Midpoint => mid,
+			TieBreak::Lowest => low,
+			TieBreak::Highest => high,
+			TieBreak::ClosestToPrevious => match previous {
+				Some(p) => p.max(low).min(high),
+				None => mid,
+			},
+			TieBreak::MaxVolume => {
+				// Volume is linear between the orders' breakpoints for piecewise linear
+				// schedules, so its maximum is at one of them or an end of the interval.
+				// Other schedules are only evaluated at those same prices.
+				let volume = |p: f64| {
+					let (dem, sup) = Auction::calc_aggs(p, bids, asks);
+					dem.min(sup)
+				};
+				// Favour the midpoint when candidates trade the same volume
+				Auction::breakpoints_between(low, high, bids, asks).iter().fold((mid, volume(mid)), |best, p| {
+					let v = volume(*p);
+					if Auction::greater_than_e(&v, &best.1) { (*p, v) } else { best }
+				}).0
+			},
+		}
+	}
+
+	/// Checks the books for reasons an auction cannot trade before searching for a price
+	fn check_books(bids: &BookSnapshot, asks: &BookSnapshot) -> Option<ClearingOutcome> {
+		if bids.is_empty() {
+			return Some(ClearingOutcome::EmptyBids { num_asks: asks.len() });
+		}
+		if asks.is_empty() {
+			return Some(ClearingOutcome::EmptyAsks { num_bids: bids.len() });
+		}
+
+		let best_bid = bids.max_price.unwrap_or(f64::MIN);
+		let best_ask = asks.min_price.unwrap_or(f64::MAX);
+		if best_bid < best_ask {
+			return Some(ClearingOutcome::NoOverlap { best_bid, best_ask });
+		}
+		None
+	}
+
+	/// Calculates the market clearing price exactly for piecewise linear orders. Every
+	/// order's schedule only bends or steps at its p_low, p_high and the breakpoints of
+	/// its ScheduleSpec, so the aggregate excess demand is linear between consecutive
+	/// breakpoints. The breakpoints are sorted, the segment where excess demand changes
+	/// sign is found, and the crossing is solved on that line. If supply and demand
+	/// coincide over an interval the midpoint is returned. Limit and step orders step
+	/// at their prices, so the curves may cross on a vertical segment at a breakpoint,
+	/// in which case that breakpoint is the clearing price. Books holding schedules
+	/// that aren't piecewise linear, or a solved price where supply and demand still
+	/// differ, are cleared with bs_cross instead.
+	pub fn exact_cross(bids: &BookSnapshot, asks: &BookSnapshot) -> ClearingOutcome {
+		if let Some(outcome) = Auction::check_books(bids, asks) {
+			return outcome;
+		}
+
+		let mut breakpoints = Vec::<f64>::new();
+		for order in bids.orders.iter().chain(asks.orders.iter()) {
+			match order.schedule.breakpoints() {
+				Some(points) => breakpoints.extend(points.into_iter()
+					.filter(|p| *p > order.p_low && *p < order.p_high)
+					.chain(vec![order.p_low, order.p_high])),
+				None => return Auction::bs_cross(bids, asks),
+			}
+		}
+		breakpoints.sort_by(|a, b| a.total_cmp(b));
+		breakpoints.dedup();
+
+		// Excess demand at each breakpoint is non-increasing in price
+		let excess_at = |p: f64| {
+			let (dem, sup) = Auction::aggs_of(p, &bids.orders, &asks.orders);
+			dem - sup
+		};
+		let excess: Vec<f64> = breakpoints.iter().map(|p| excess_at(*p)).collect();
+		let cross_at = |price: f64| {
+			let (demand, supply) = Auction::aggs_of(price, &bids.orders, &asks.orders);
+			ClearingOutcome::Cross { price, demand, supply, iterations: breakpoints.len() }
+		};
+
+		// The first breakpoint where supply meets or exceeds demand
+		let i = match excess.iter().position(|e| *e < EPSILON) {
+			Some(i) => i,
+			None => {
+				// Demand exceeds supply at every breakpoint. Above the last breakpoint every
+				// bid has stopped, so if supply remains the curves cross on a step there.
+				let last_price = *breakpoints.last().unwrap();
+				if excess_at(last_price + 1.0) < -EPSILON {
+					return cross_at(last_price);
+				}
+				let (demand, supply) = Auction::aggs_of(last_price, &bids.orders, &asks.orders);
+				return ClearingOutcome::NoConvergence { last_price, demand, supply, iterations: breakpoints.len() };
+			},
+		};
+
+		if excess[i].abs() < EPSILON {
+			// Supply and demand are equal, possibly over an interval of breakpoints
+			let mut j = i;
+			while j + 1 < excess.len() && excess[j + 1].abs() < EPSILON {
+				j += 1;
+			}
+			let price = (breakpoints[i] + breakpoints[j]) / 2.0;
+			let (demand, supply) = Auction::aggs_of(price, &bids.orders, &asks.orders);
+			if i == j {
+				return ClearingOutcome::Cross { price, demand, supply, iterations: breakpoints.len() };
+			}
+			return ClearingOutcome::Interval { low: breakpoints[i], high: breakpoints[j], price, volume: demand.min(supply) };
+		}
+
+		if i == 0 {
+			// Below the first breakpoint no ask has started, so if demand remains the
+			// curves cross on a step at the first breakpoint
+			if excess_at(breakpoints[0] - 1.0) > EPSILON {
+				return cross_at(breakpoints[0]);
+			}
+			// Supply exceeds demand at every price
+			let best_bid = bids.max_price.unwrap_or(f64::MIN);
+			let best_ask = asks.min_price.unwrap_or(f64::MAX);
+			return ClearingOutcome::NoOverlap { best_bid, best_ask };
+		}
+
+		// Limit orders (p_low == p_high) make the curves jump at their breakpoint, so
+		// the excess at a breakpoint can differ from its limits either side. Inside the
+		// segment excess is linear, so extrapolate its limits from two interior points.
+		let (p0, p1) = (breakpoints[i - 1], breakpoints[i]);
+		let (m0, m1) = (excess_at(p0 + (p1 - p0) / 3.0), excess_at(p0 + 2.0 * (p1 - p0) / 3.0));
+		let (e0, e1) = (2.0 * m0 - m1, 2.0 * m1 - m0);
+
+		if e0.abs() < EPSILON && e1.abs() < EPSILON {
+			// Supply and demand are equal inside the segment but not at either end
+			let price = (p0 + p1) / 2.0;
+			let (demand, supply) = Auction::aggs_of(price, &bids.orders, &asks.orders);
+			return ClearingOutcome::Interval { low: p0, high: p1, price, volume: demand.min(supply) };
+		}
+		if e0 < EPSILON {
+			// Demand steps down past supply at p0
+			return cross_at(p0);
+		}
+		if e1 > -EPSILON {
+			// Supply steps up past demand at p1
+			return cross_at(p1);
+		}
+
+		// Solve the linear segment between the breakpoints straddling the cross
+		match cross_at(p0 + e0 * (p1 - p0) / (e0 - e1)) {
+			ClearingOutcome::Cross { demand, supply, .. } if !Auction::is_cleared(demand, supply) => Auction::bs_cross(bids, asks),
+			outcome => outcome,
+		}
+	}
+
+	// true if 'demand' and 'supply' are equal up to rounding error relative to their size
+	fn is_cleared(demand: f64, supply: f64) -> bool {
+		(demand - supply).abs() <= EPSILON * demand.max(supply).max(1.0)
+	}
+
 	/// Calculates the market clearing price from the bids and asks books. Uses a 
 	/// binary search to find the intersection point between the aggregates supply and 
 	/// demand curves. 
-	pub fn bs_cross(bids: Arc<Book>, asks: Arc<Book>) -> Option<f64> {
-		// get_price_bounds obtains locks on the book's prices
-	    let (mut left, mut right) = Auction::get_price_bounds(Arc::clone(&bids), Arc::clone(&asks));
+	pub fn bs_cross(bids: &BookSnapshot, asks: &BookSnapshot) -> ClearingOutcome {
+		if let Some(outcome) = Auction::check_books(bids, asks) {
+			return outcome;
+		}
+
+	    let (mut left, mut right) = Auction::get_price_bounds(bids, asks);
 	    let max_iters = 1000;
 	    let mut curr_iter = 0;
-	    println!("Min Book price: {}, Max Book price: {}", left, right);
+	    let (mut dem, mut sup) = (0.0, 0.0);
 	    while left < right {
 	    	curr_iter += 1;
 	    	// Find a midpoint with the correct price tick precision
 	    	let index: f64 = (left + right) / 2.0;
+	    	if index == left || index == right {
+	    		// The bounds can't be split any further, so they have closed on a vertical step
+	    		// in one of the curves, such as a limit order's price. Trade at whichever side
+	    		// fills the most volume, unless the other side is the step's breakpoint and
+	    		// clears with less excess there, as exact_cross would find.
+	    		let (l_dem, l_sup) = Auction::calc_aggs(left, bids, asks);
+	    		let (r_dem, r_sup) = Auction::calc_aggs(right, bids, asks);
+	    		let (mut best, mut other) = ((left, l_dem, l_sup), (right, r_dem, r_sup));
+	    		if l_dem.min(l_sup) < r_dem.min(r_sup) {
+	    			std::mem::swap(&mut best, &mut other);
+	    		}
+	    		let is_breakpoint = |p: f64| bids.orders.iter().chain(asks.orders.iter())
+	    			.any(|o| o.p_low == p || o.p_high == p || o.schedule.breakpoints().unwrap_or_default().contains(&p));
+	    		if is_breakpoint(other.0) && (other.1 - other.2).abs() < (best.1 - best.2).abs() {
+	    			best = other;
+	    		}
+	    		let (price, demand, supply) = best;
+	    		return ClearingOutcome::Cross { price, demand, supply, iterations: curr_iter };
+	    	}
 	    	// Calculate the aggregate supply and demand at this price
-	    	let (dem, sup) = Auction::calc_aggs(index, Arc::clone(&bids), Arc::clone(&asks));
-	    	// println!("price_index: {}, dem: {}, sup: {}", index, dem, sup);
+	    	let aggs = Auction::calc_aggs(index, bids, asks);
+	    	dem = aggs.0;
+	    	sup = aggs.1;
 
 	    	if Auction::greater_than_e(&dem, &sup) {  		// dev > sup
 	    		// We are left of the crossing point
@@ -67,96 +593,163 @@ impl Auction {
 	    		// We are right of the crossing point
 	    		right = index;
 	    	} else {
-	    		println!("Found cross at: {}", index);
-	    		return Some(index);
+	    		// Supply equals demand, widen the search to either side in case they
+	    		// are equal over an interval rather than a single point
+	    		let (low, high) = Auction::bs_interval(left, index, right, bids, asks);
+	    		if Auction::greater_than_e(&high, &low) {
+	    			return ClearingOutcome::Interval { low, high, price: (low + high) / 2.0, volume: dem.min(sup) };
+	    		}
+	    		return ClearingOutcome::Cross { price: index, demand: dem, supply: sup, iterations: curr_iter };
 	    	}
 
 	    	if curr_iter == max_iters {
-	    		println!("Trouble finding cross in max iterations, got: {}", index);
-	    		return Some(index);
+	    		return ClearingOutcome::NoConvergence { last_price: index, demand: dem, supply: sup, iterations: curr_iter };
 	    	}
 	    }
-	    None
+
+	    // Every order shares one price, so there is nothing to search
+	    let (l_dem, l_sup) = Auction::calc_aggs(left, bids, asks);
+	    if l_dem.min(l_sup) > EPSILON {
+	    	return ClearingOutcome::Cross { price: left, demand: l_dem, supply: l_sup, iterations: curr_iter };
+	    }
+	    ClearingOutcome::NoConvergence { last_price: left, demand: dem, supply: sup, iterations: curr_iter }
 	}
 
-	/// Schedules an auction to run on an interval determined by the duration parameter in milliseconds.
+	// Bisects [left, index] and [index, right] for the edges of the interval around
+	// 'index' where aggregate supply and demand are equal.
+	fn bs_interval(left: f64, index: f64, right: f64, bids: &BookSnapshot, asks: &BookSnapshot) -> (f64, f64) {
+		let is_equal = |p: f64| {
+			let (dem, sup) = Auction::calc_aggs(p, bids, asks);
+			!Auction::greater_than_e(&dem, &sup) && !Auction::less_than_e(&dem, &sup)
+		};
+		let max_iters = 100;
+
+		// Lower edge: 'hi' always has equal supply and demand
+		let (mut lo, mut hi) = (left, index);
+		for _ in 0..max_iters {
+			if !Auction::greater_than_e(&hi, &lo) { break; }
+			let mid = (lo + hi) / 2.0;
+			if is_equal(mid) { hi = mid; } else { lo = mid; }
+		}
+		let low = if is_equal(lo) { lo } else { hi };
+
+		// Upper edge: 'lo' always has equal supply and demand
+		let (mut lo, mut hi) = (index, right);
+		for _ in 0..max_iters {
+			if !Auction::greater_than_e(&hi, &lo) { break; }
+			let mid = (lo + hi) / 2.0;
+			if is_equal(mid) { lo = mid; } else { hi = mid; }
+		}
+		let high = if is_equal(hi) { hi } else { lo };
+
+		(low, high)
+	}
+
+	/// Schedules auctions to run whenever the scheduler's policy says the next batch is due.
+	/// The fills of each auction are settled in the ledger over the actual duration of the batch,
+	/// and orders that have traded their full quantity are removed from the books. Every
+	/// auction is recorded in the history whether or not a cross was found. Orders whose
+	/// time in force has run out are expired at the batch boundary. The auction clears
+	/// snapshots of the books, so orders keep being processed while it runs, and only
+	/// the orders in the snapshots count it towards their time in force.
 	/// Outputs a task that will be dispatched asynchronously via the controller module.
-	pub fn async_auction_task(bids: Arc<Book>, asks: Arc<Book>, state: Arc<Mutex<State>>, duration: u64) -> Task {
+	#[allow(clippy::too_many_arguments)]
+	pub fn async_auction_task(bids: Arc<Book>, asks: Arc<Book>, ledger: Arc<Ledger>, 
+		                      events: Arc<EventQueue>, history: Arc<AuctionHistory>,
+		                      config: Arc<ExchangeConfig>, 
+		                      scheduler: Arc<Scheduler>, guard: Arc<SelfTradeGuard>) -> Task {
+		// The last clearing price, for tie breaking to the closest price
+		let previous = Mutex::new(None);
 		Task::rpt_task(move || {
-			{
-	    		// Obtain lock on the global state and switch to Auction mode, will stop
-	    		// the queue from being processed.
-	    		let mut state = state.lock().unwrap();
-	    		*state = State::Auction;
+			// Length of the batch that just ended in milliseconds
+			let duration = match scheduler.poll() {
+				Some(elapsed) => elapsed,
+				None => return,
+			};
+	    	let boundary = scheduler.last_boundary();
+	    	println!("Starting Auction @{:?} ({})", boundary, scheduler.policy_name());
+	    	// Orders that timed out during the batch don't take part in the auction
+	    	events.push_all(bids.expire(boundary));
+	    	events.push_all(asks.expire(boundary));
+	    	// Orders the guard rejected or cancelled on arrival during the batch
+	    	let removed = guard.take();
+	    	let (bid_orders, ask_orders) = (bids.snapshot(), asks.snapshot());
+	    	let (num_bids, num_asks) = (bid_orders.len(), ask_orders.len());
+	    	let last_price = *previous.lock().unwrap();
+	    	let mut result = Auction::run_auction(&bid_orders, &ask_orders, &config, last_price);
+	    	if !removed.is_empty() {
+	    		result.self_trade = self_trade::report_removed(&removed, &result);
+	    	}
+	    	if result.clearing_price.is_some() {
+	    		*previous.lock().unwrap() = result.clearing_price;
 	    	}
-	    	println!("Starting Auction @{:?}", get_time());
-	    	if let Some(cross_price) = Auction::bs_cross(Arc::clone(&bids), Arc::clone(&asks)) {
-	    		println!("Found Cross at @{:?} \nP = {}\n", get_time(), cross_price);
+	    	// Cap fills at each order's remaining quantity, then remove exhausted orders
+	    	bids.cap_fills(&mut result.bid_fills, duration);
+	    	asks.cap_fills(&mut result.ask_fills, duration);
+	    	result.ration();
+	    	let mut completed = bids.deplete(&result.bid_fills, duration);
+	    	completed.extend(asks.deplete(&result.ask_fills, duration));
+	    	events.push_all(completed);
+	    	// Deadline orders spread what they have left over the time until their deadline
+	    	events.push_all(bids.pace(boundary, duration));
+	    	events.push_all(asks.pace(boundary, duration));
+	    	// Orders that have taken part in their last auction expire now
+	    	bids.count_auction(&bid_orders.order_ids());
+	    	asks.count_auction(&ask_orders.order_ids());
+	    	events.push_all(bids.expire(boundary));
+	    	events.push_all(asks.expire(boundary));
+	    	if let Some(cross_price) = result.clearing_price {
+	    		println!("Found Cross at @{:?} \nP = {}, volume = {}, imbalance = {}\n", 
+	    			get_time(), cross_price, result.volume, result.imbalance);
 	    	} else {
-	    		println!("Error, Cross not found\n");
+	    		println!("No cross found: {:?}\n", result.outcome);
 	    	}
-	    	
-	    	{
-	    		// Change the state back to process to allow the books to be mutated again
-	    		let mut state = state.lock().unwrap();
-	    		*state = State::Process;
+	    	if !result.self_trade.is_empty() {
+	    		println!("Prevented {} self trades, volume = {}", result.self_trade.prevented.len(), result.self_trade.volume);
 	    	}
-		}, duration)
+	    	if let Err(e) = history.record(&result, num_bids, num_asks) {
+	    		println!("ERROR: Couldn't persist auction history: {}", e);
+	    	}
+	    	let trades = ledger.settle(&result, duration);
+	    	println!("Settled {} trades in batch {}", trades.len(), ledger.num_batches());
+		}, POLL_INTERVAL)
 	}
 
-	pub fn get_price_bounds(bids: Arc<Book>, asks: Arc<Book>) -> (f64, f64) {		
-		let bids_min: f64 = bids.get_min_price();
-		let bids_max: f64 = bids.get_max_price();
-		let asks_min: f64 = asks.get_min_price();
-		let asks_max: f64 = asks.get_max_price();
+	pub fn get_price_bounds(bids: &BookSnapshot, asks: &BookSnapshot) -> (f64, f64) {		
+		let bids_min: f64 = bids.min_price.unwrap_or(f64::MAX);
+		let bids_max: f64 = bids.max_price.unwrap_or(0.0);
+		let asks_min: f64 = asks.min_price.unwrap_or(f64::MAX);
+		let asks_max: f64 = asks.max_price.unwrap_or(0.0);
 
 		(Auction::min_float(&bids_min, &asks_min), Auction::max_float(&bids_max, &asks_max))
 	}
 
+	// Ignores NaN so a bad price can't panic the auction
 	fn max_float(a: &f64, b: &f64) -> f64 {
-	    match a.partial_cmp(b).unwrap() {
-			std::cmp::Ordering::Less => *b,
-			std::cmp::Ordering::Greater => *a,
-			std::cmp::Ordering::Equal => *a
-		}
+	    a.max(*b)
 	}
 
+	// Ignores NaN so a bad price can't panic the auction
 	fn min_float(a: &f64, b: &f64) -> f64 {
-	    match a.partial_cmp(b).unwrap() {
-			std::cmp::Ordering::Less => *a,
-			std::cmp::Ordering::Greater => *b,
-			std::cmp::Ordering::Equal => *a
-		}
+	    a.min(*b)
 	}
 
 	// true if a > b
 	pub fn greater_than_e(a: &f64, b: &f64) -> bool {
 		let a = a.abs();
 		let b = b.abs();
-	    if (a - b).abs() > EPSILON && a - b > 0.0 {
-	    	return true;
-	    } else {
-	    	return false;
-	    }
+	    (a - b).abs() > EPSILON && a - b > 0.0
 	}
 
 	// true if a < b
 	pub fn less_than_e(a: &f64, b: &f64) -> bool {
 		let a = a.abs();
 		let b = b.abs();
-	    if (a - b).abs() > EPSILON && a - b < 0.0 {
-	    	return true;
-	    } else {
-	    	return false;
-	    }
+	    (a - b).abs() > EPSILON && a - b < 0.0
 	}
 
 	pub	fn equal_e(a: &f64, b: &f64) -> bool {
-	    if (a - b).abs() < EPSILON {
-	    	return true;
-	    } else {
-	    	return false;
-	    }
+	    (a - b).abs() < EPSILON
 	}
 }
 
@@ -0,0 +1,151 @@
+use crate::order::{Order, TradeType};
+use crate::exchange::validation::check_params;
+
+use std::fmt;
+
+
+/// A way a Book breaks one of its invariants, found by auditing it.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum Discrepancy {
+	/// The order at 'position' has a lower p_high than the order before it
+	Unsorted { position: usize, p_high: f64, previous: f64 },
+	/// Several orders share the same exchange assigned order_id
+	DuplicateId { order_id: u64, count: usize },
+	/// One of the Book's indexes disagrees with the orders it indexes
+	IndexMismatch { index: &'static str, detail: String },
+	/// The Book's min or max price doesn't match its orders
+	BoundsMismatch { bound: &'static str, reported: Option<f64>, actual: Option<f64> },
+	/// An order has a field the exchange should never have accepted
+	InvalidOrder { trader_id: String, order_id: Option<u64>, reason: String },
+}
+
+impl fmt::Display for Discrepancy {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Discrepancy::Unsorted { position, p_high, previous } => {
+				write!(f, "order {} has p_high {} below the previous {}", position, p_high, previous)
+			},
+			Discrepancy::DuplicateId { order_id, count } => write!(f, "order_id {} is used by {} orders", order_id, count),
+			Discrepancy::IndexMismatch { index, detail } => write!(f, "{} index: {}", index, detail),
+			Discrepancy::BoundsMismatch { bound, reported, actual } => {
+				write!(f, "{} price is {:?} but the orders give {:?}", bound, reported, actual)
+			},
+			Discrepancy::InvalidOrder { trader_id, order_id, reason } => {
+				write!(f, "order {:?} of {:?}: {}", order_id, trader_id, reason)
+			},
+		}
+	}
+}
+
+/// The result of auditing a Book.
+/// book_type: TradeType{Bid, Ask} -> the type of the audited Book
+/// version: u64 -> the Book's version when it was audited
+/// num_orders: usize -> number of orders in the Book
+/// discrepancies: Vec<Discrepancy> -> every broken invariant, empty if the Book is consistent
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditReport {
+	pub book_type: TradeType,
+	pub version: u64,
+	pub num_orders: usize,
+	pub discrepancies: Vec<Discrepancy>,
+}
+
+impl AuditReport {
+	/// True if the Book holds all of its invariants
+	pub fn is_ok(&self) -> bool {
+		self.discrepancies.is_empty()
+	}
+}
+
+impl fmt::Display for AuditReport {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{:?} book v{} with {} orders: {} discrepancies", 
+			self.book_type, self.version, self.num_orders, self.discrepancies.len())?;
+		for d in self.discrepancies.iter() {
+			write!(f, "\n\t{}", d)?;
+		}
+		Ok(())
+	}
+}
+
+/// Checks a single order's fields are valid for a Book of 'book_type'
+pub fn check_order(book_type: &TradeType, order: &Order) -> Vec<Discrepancy> {
+	let mut reasons = Vec::<String>::new();
+	if order.trade_type != *book_type {
+		reasons.push(format!("{:?} order in the {:?} book", order.trade_type, book_type));
+	}
+	if let Err(reason) = check_params(&order.trader_id, order.p_low, order.p_high, order.u_max, order.quantity) {
+		reasons.push(reason.to_string());
+	}
+	match (order.quantity, order.remaining) {
+		(Some(q), Some(r)) if !(0.0..=q).contains(&r) => {
+			reasons.push(format!("remaining {} is outside [0, {}]", r, q));
+		},
+		(Some(_), None) | (None, Some(_)) => reasons.push(String::from("quantity and remaining disagree")),
+		_ => {},
+	}
+	if order.deadline.is_some() && order.quantity.is_none() {
+		reasons.push(String::from("deadline order has no quantity"));
+	}
+
+	reasons.into_iter()
+		.map(|reason| Discrepancy::InvalidOrder { trader_id: order.trader_id.clone(), order_id: order.order_id, reason })
+		.collect()
+}
+
+/// Checks orders listed in Book order are sorted by p_high and that the reported
+/// min and max prices match them
+pub fn check_sorted_and_bounds<'a, I>(orders: I, min_price: Option<f64>, max_price: Option<f64>) -> Vec<Discrepancy>
+where I: Iterator<Item = &'a Order>
+{
+	let mut discrepancies = Vec::<Discrepancy>::new();
+	let mut previous: Option<f64> = None;
+	let (mut min, mut max): (Option<f64>, Option<f64>) = (None, None);
+	for (position, order) in orders.enumerate() {
+		if let Some(prev) = previous {
+			if order.p_high < prev {
+				discrepancies.push(Discrepancy::Unsorted { position, p_high: order.p_high, previous: prev });
+			}
+		}
+		previous = Some(order.p_high);
+		min = Some(min.map_or(order.p_low, |m| m.min(order.p_low)));
+		max = Some(max.map_or(order.p_high, |m| m.max(order.p_high)));
+	}
+
+	if min != min_price {
+		discrepancies.push(Discrepancy::BoundsMismatch { bound: "min", reported: min_price, actual: min });
+	}
+	if max != max_price {
+		discrepancies.push(Discrepancy::BoundsMismatch { bound: "max", reported: max_price, actual: max });
+	}
+	discrepancies
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::order::OrderType;
+	use crate::order::schedule::ScheduleSpec;
+
+	#[test]
+	fn test_check_orders() {
+		let new_bid = |p_low: f64, p_high: f64| Order::new(String::from("trader"), OrderType::Enter, TradeType::Bid,
+			p_low, p_high, 10.0, ScheduleSpec::PiecewiseLinear);
+		assert!(check_order(&TradeType::Bid, &new_bid(1.0, 2.0)).is_empty());
+		assert_eq!(check_order(&TradeType::Ask, &new_bid(1.0, 2.0)).len(), 1);
+		assert_eq!(check_order(&TradeType::Bid, &new_bid(3.0, 2.0)).len(), 1);
+
+		let mut overdrawn = new_bid(1.0, 2.0).with_quantity(5.0);
+		overdrawn.remaining = Some(-1.0);
+		assert_eq!(check_order(&TradeType::Bid, &overdrawn).len(), 1);
+
+		let orders = [new_bid(1.0, 5.0), new_bid(0.0, 3.0)];
+		let discrepancies = check_sorted_and_bounds(orders.iter(), Some(1.0), Some(5.0));
+		assert_eq!(discrepancies, vec![
+			Discrepancy::Unsorted { position: 1, p_high: 3.0, previous: 5.0 },
+			Discrepancy::BoundsMismatch { bound: "min", reported: Some(1.0), actual: Some(0.0) },
+		]);
+		assert!(check_sorted_and_bounds(Vec::<Order>::new().iter(), None, None).is_empty());
+	}
+}
@@ -0,0 +1,99 @@
+use crate::exchange::auction::{ClearingMethod, TieBreak};
+use crate::exchange::self_trade::SelfTradePrevention;
+
+
+/// Settings that decide how an exchange runs its auctions.
+/// clearing_method: ClearingMethod{BinarySearch, Exact} -> algorithm used to find the clearing price
+/// tie_break: TieBreak -> rule for picking the price when supply and demand are equal over an interval
+/// tick_size: Option<f64> -> price grid that order prices and clearing prices are rounded to
+/// lot_size: Option<f64> -> quantity increment that order quantities are rounded down to
+/// self_trade: SelfTradePrevention -> how traders are stopped from trading with themselves
+#[derive(Debug, Clone)]
+pub struct ExchangeConfig {
+	pub clearing_method: ClearingMethod,
+	pub tie_break: TieBreak,
+	pub tick_size: Option<f64>,
+	pub lot_size: Option<f64>,
+	pub self_trade: SelfTradePrevention,
+}
+
+impl Default for ExchangeConfig {
+	fn default() -> Self {
+		ExchangeConfig {
+			clearing_method: ClearingMethod::BinarySearch,
+			tie_break: TieBreak::Midpoint,
+			tick_size: None,
+			lot_size: None,
+			self_trade: SelfTradePrevention::Off,
+		}
+	}
+}
+
+impl ExchangeConfig {
+	/// Rounds a price to the nearest tick, or returns it unchanged without a tick size
+	pub fn snap_price(&self, price: f64) -> f64 {
+		match self.tick_size {
+			Some(tick) if tick > 0.0 => grid_point((price / tick).round(), tick),
+			_ => price,
+		}
+	}
+
+	/// Rounds a quantity down to a whole number of lots, or returns it unchanged 
+	/// without a lot size. Only the total quantity is traded in lots: u_max is a rate 
+	/// and each fill is that rate times the batch duration, so a rate in whole lots 
+	/// still wouldn't give fills in whole lots. u_max is left as the trader sent it.
+	pub fn snap_quantity(&self, quantity: f64) -> f64 {
+		match self.lot_size {
+			Some(lot) if lot > 0.0 => {
+				// A quantity a hair below a whole lot from float error still counts as that lot
+				let lots = if on_grid(quantity, lot) { (quantity / lot).round() } else { (quantity / lot).floor() };
+				grid_point(lots, lot)
+			},
+			_ => quantity,
+		}
+	}
+}
+
+// True if x is a whole number of steps, allowing for float error relative to the step
+fn on_grid(x: f64, step: f64) -> bool {
+	((x / step).round() * step - x).abs() <= step * 1e-9
+}
+
+// The value a whole number of steps from zero. Dividing by the reciprocal of a step
+// below one is exact for decimal steps where multiplying isn't, ie 811 / 10 is 81.1
+// but 811 * 0.1 is 81.10000000000001
+fn grid_point(steps: f64, step: f64) -> f64 {
+	if step < 1.0 { steps / step.recip() } else { steps * step }
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_snap_to_grid() {
+		let config = ExchangeConfig { tick_size: Some(0.25), lot_size: Some(10.0), ..ExchangeConfig::default() };
+		assert_eq!(config.snap_price(81.09), 81.0);
+		assert_eq!(config.snap_price(81.13), 81.25);
+		assert_eq!(config.snap_quantity(99.0), 90.0);
+		assert_eq!(config.snap_quantity(100.0), 100.0);
+
+		// Snapped values land exactly on grids that aren't exact in binary
+		let config = ExchangeConfig { tick_size: Some(0.1), lot_size: Some(0.1), ..ExchangeConfig::default() };
+		assert_eq!(config.snap_price(81.13), 81.1);
+		assert_eq!(config.snap_price(246.84), 246.8);
+		assert_eq!(config.snap_quantity(0.3), 0.3);
+		assert_eq!(config.snap_quantity(0.79), 0.7);
+
+		// Steps that aren't a whole fraction still land on the grid within float error
+		let config = ExchangeConfig { tick_size: Some(0.3), lot_size: Some(0.3), ..ExchangeConfig::default() };
+		assert!(on_grid(config.snap_price(81.13), 0.3));
+		assert_eq!(config.snap_quantity(0.3 * 3.0), config.snap_quantity(0.9));
+		assert!(on_grid(81.10000000000001, 0.1) && !on_grid(81.13, 0.1));
+
+		let config = ExchangeConfig::default();
+		assert_eq!(config.snap_price(81.09), 81.09);
+		assert_eq!(config.snap_quantity(99.0), 99.0);
+	}
+}
@@ -0,0 +1,175 @@
+use crate::order::TradeType;
+use crate::exchange::self_trade::PreventionAction;
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+
+/// Notifications the exchange generates for traders about their orders. Traders
+/// collect them with an events query, serialized with their variant as "event".
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ExchangeEvent {
+	/// The order traded its full quantity and was removed from its Book
+	Completed {
+		trader_id: String,
+		order_id: Option<u64>,
+		trade_type: TradeType,
+		quantity: f64,
+		timestamp: Duration,
+	},
+	/// The order's time in force ran out and it was removed from its Book
+	Expired {
+		trader_id: String,
+		order_id: Option<u64>,
+		trade_type: TradeType,
+		timestamp: Duration,
+	},
+	/// The exchange rewrote a deadline order's rate after an auction
+	Progress {
+		trader_id: String,
+		order_id: Option<u64>,
+		trade_type: TradeType,
+		filled: f64,
+		remaining: f64,
+		u_max: f64,
+		timestamp: Duration,
+	},
+	/// A deadline order's deadline arrived and it was removed from its Book with
+	/// 'shortfall' of its quantity untraded
+	DeadlineReached {
+		trader_id: String,
+		order_id: Option<u64>,
+		trade_type: TradeType,
+		filled: f64,
+		shortfall: f64,
+		timestamp: Duration,
+	},
+	/// The order was removed from its Book to stop the trader trading with themselves
+	SelfTradePrevented {
+		trader_id: String,
+		order_id: Option<u64>,
+		trade_type: TradeType,
+		action: PreventionAction,
+		timestamp: Duration,
+	},
+}
+
+impl ExchangeEvent {
+	/// The trader that the event is addressed to
+	pub fn trader_id(&self) -> &str {
+		match self {
+			ExchangeEvent::Completed { trader_id, .. } => trader_id,
+			ExchangeEvent::Expired { trader_id, .. } => trader_id,
+			ExchangeEvent::Progress { trader_id, .. } => trader_id,
+			ExchangeEvent::DeadlineReached { trader_id, .. } => trader_id,
+			ExchangeEvent::SelfTradePrevented { trader_id, .. } => trader_id,
+		}
+	}
+}
+
+/// Most events the queue holds before it starts dropping the oldest
+pub const EVENT_CAPACITY: usize = 10_000;
+
+/// A threadsafe FIFO queue of events waiting to be delivered to traders. Once it
+/// holds 'capacity' events the oldest are dropped to make room, so traders that
+/// never collect their events can't grow it without bound.
+pub struct EventQueue {
+	items: Mutex<VecDeque<ExchangeEvent>>,
+	capacity: usize,
+	dropped: AtomicUsize,
+}
+
+impl Default for EventQueue {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl EventQueue {
+	pub fn new() -> EventQueue {
+		EventQueue::with_capacity(EVENT_CAPACITY)
+	}
+
+	pub fn with_capacity(capacity: usize) -> EventQueue {
+		EventQueue {
+			items: Mutex::new(VecDeque::<ExchangeEvent>::new()),
+			capacity,
+			dropped: AtomicUsize::new(0),
+		}
+	}
+
+	// New events are pushed to the end of the queue
+	pub fn push(&self, event: ExchangeEvent) {
+		self.push_all(vec![event]);
+	}
+
+	pub fn push_all(&self, events: Vec<ExchangeEvent>) {
+		let mut items = self.items.lock().unwrap();
+		items.extend(events);
+		let excess = items.len().saturating_sub(self.capacity);
+		if excess > 0 {
+			items.drain(..excess);
+			self.dropped.fetch_add(excess, Ordering::Relaxed);
+			println!("ERROR: event queue full, dropped {} oldest events", excess);
+		}
+	}
+
+	// Empties the queue returning events in order of arrival
+	pub fn pop_all(&self) -> Vec<ExchangeEvent> {
+		self.items.lock().unwrap().drain(..).collect()
+	}
+
+	// Removes and returns only the events addressed to 'trader_id'
+	pub fn pop_for(&self, trader_id: &str) -> Vec<ExchangeEvent> {
+		let mut items = self.items.lock().unwrap();
+		let (theirs, rest): (VecDeque<_>, VecDeque<_>) = items.drain(..)
+			.partition(|e| e.trader_id() == trader_id);
+		*items = rest;
+		theirs.into()
+	}
+
+	// Number of events dropped undelivered because the queue was full
+	pub fn dropped(&self) -> usize {
+		self.dropped.load(Ordering::Relaxed)
+	}
+
+	pub fn len(&self) -> usize {
+		self.items.lock().unwrap().len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn expired(trader_id: &str, order_id: u64) -> ExchangeEvent {
+		ExchangeEvent::Expired {
+			trader_id: String::from(trader_id),
+			order_id: Some(order_id),
+			trade_type: TradeType::Bid,
+			timestamp: Duration::from_millis(1000),
+		}
+	}
+
+	#[test]
+	fn test_bounded_queue() {
+		let events = EventQueue::with_capacity(3);
+		events.push(expired("t1", 1));
+		events.push_all(vec![expired("t2", 2), expired("t1", 3), expired("t2", 4)]);
+		assert_eq!(events.len(), 3);
+		assert_eq!(events.dropped(), 1);
+
+		// The oldest event was the one dropped and the rest keep their order
+		assert_eq!(events.pop_for("t2"), vec![expired("t2", 2), expired("t2", 4)]);
+		assert_eq!(events.pop_all(), vec![expired("t1", 3)]);
+		assert!(events.is_empty());
+	}
+}
@@ -0,0 +1,223 @@
+use crate::exchange::order_processing::JsonOrder;
+use crate::exchange::queue::Queue;
+use crate::exchange::config::ExchangeConfig;
+use crate::exchange::events::EventQueue;
+use crate::exchange::scheduling::{Scheduler, STATUS_BOUNDARIES};
+use crate::exchange::validation::RejectReason;
+use crate::exchange::self_trade::SelfTradeGuard;
+use crate::exchange::history::AuctionHistory;
+use crate::exchange::settlement::Ledger;
+use crate::exchange::order_book::Book;
+use crate::exchange::auction::{Auction, CurveOptions, CurveSampling};
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::Value;
+
+
+/// Most auction records a history query can return
+pub const HISTORY_LIMIT: usize = 1000;
+/// Auction records a history query returns when it doesn't ask for a number
+pub const HISTORY_RECORDS: usize = 100;
+/// Most grid points a curves query can ask for
+pub const CURVE_POINTS_LIMIT: usize = 1000;
+
+/// Requests a trader can send in place of an order, tagged by "query".
+/// Events -> takes the events waiting for 'trader_id' off the event queue
+/// Schedule -> the active auction policy, the next batch boundary and the 'last'
+/// most recent boundaries
+/// History -> the 'last' most recent auction records after 'since_seq' recorded between
+/// 'from' and 'to' in milliseconds since the UNIX epoch
+/// Account -> the settled position and cash of 'trader_id'
+/// Trades -> every trade settled for 'trader_id' in order of settlement
+/// Curves -> the aggregate demand and supply curves of the books, grid sampling is
+/// capped at CURVE_POINTS_LIMIT points
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(tag = "query", rename_all = "snake_case")]
+pub enum Query {
+	Events { trader_id: String },
+	Schedule { last: Option<usize> },
+	History { last: Option<usize>, since_seq: Option<u64>, from: Option<u64>, to: Option<u64> },
+	Account { trader_id: String },
+	Trades { trader_id: String },
+	Curves(CurveOptions),
+}
+
+/// The parts of the exchange a trader's connection can reach. Every message is
+/// either an order, which is validated and queued, or a query answered from the
+/// exchange's state. Both the TCP and websocket servers reply with handle().
+/// queue: Arc<Queue> -> queue that accepted orders are added to
+/// config: Arc<ExchangeConfig> -> settings orders are validated and rounded with
+/// events: Arc<EventQueue> -> events waiting to be delivered to traders
+/// scheduler: Arc<Scheduler> -> decides when auctions run and reports the batch boundaries
+/// guard: Arc<SelfTradeGuard> -> stops traders trading with themselves as orders arrive
+/// history: Arc<AuctionHistory> -> results of past auctions
+/// ledger: Arc<Ledger> -> settled accounts and trades
+/// bids: Arc<Book> -> the bids the curves are computed from
+/// asks: Arc<Book> -> the asks the curves are computed from
+pub struct Gateway {
+	pub queue: Arc<Queue>,
+	pub config: Arc<ExchangeConfig>,
+	pub events: Arc<EventQueue>,
+	pub scheduler: Arc<Scheduler>,
+	pub guard: Arc<SelfTradeGuard>,
+	pub history: Arc<AuctionHistory>,
+	pub ledger: Arc<Ledger>,
+	pub bids: Arc<Book>,
+	pub asks: Arc<Book>,
+}
+
+impl Gateway {
+	#[allow(clippy::too_many_arguments)]
+	pub fn new(queue: Arc<Queue>, config: Arc<ExchangeConfig>, events: Arc<EventQueue>, scheduler: Arc<Scheduler>,
+		guard: Arc<SelfTradeGuard>, history: Arc<AuctionHistory>, ledger: Arc<Ledger>,
+		bids: Arc<Book>, asks: Arc<Book>) -> Gateway 
+	{
+		Gateway {
+			queue,
+			config,
+			events,
+			scheduler,
+			guard,
+			history,
+			ledger,
+			bids,
+			asks,
+		}
+	}
+
+	/// Handles one message from a trader and returns the reply to send back: the
+	/// order's acknowledgement, the answer to a query, or why it was rejected.
+	pub fn handle(&self, msg: Value) -> Value {
+		if msg.get("query").is_some() {
+			return match serde_json::from_value::<Query>(msg) {
+				Ok(query) => self.answer(query),
+				Err(_) => Gateway::reject(RejectReason::MalformedJson),
+			};
+		}
+
+		match JsonOrder::process_new(msg, Arc::clone(&self.queue), &self.config, Some(&self.guard)) {
+			Ok(ack) => json!({ "ack": ack }),
+			Err(reason) => Gateway::reject(reason),
+		}
+	}
+
+	/// Answers a query from the exchange's state
+	pub fn answer(&self, query: Query) -> Value {
+		match query {
+			Query::Events { trader_id } => json!({ "events": self.events.pop_for(&trader_id) }),
+			Query::Schedule { last } => json!({ "schedule": self.scheduler.status(last.unwrap_or(STATUS_BOUNDARIES)) }),
+			Query::History { last, since_seq, from, to } => {
+				let last = last.unwrap_or(HISTORY_RECORDS).min(HISTORY_LIMIT);
+				let records = self.history.select(since_seq, from.map(Duration::from_millis), to.map(Duration::from_millis), last);
+				json!({ "history": records })
+			},
+			Query::Account { trader_id } => json!({ "account": self.ledger.get_account(&trader_id).unwrap_or_default() }),
+			Query::Trades { trader_id } => json!({ "trades": self.ledger.trade_history(&trader_id) }),
+			Query::Curves(mut options) => {
+				if let CurveSampling::Grid { points } = options.sampling {
+					options.sampling = CurveSampling::Grid { points: points.min(CURVE_POINTS_LIMIT) };
+				}
+				json!({ "curves": Auction::calc_curves(Arc::clone(&self.bids), Arc::clone(&self.asks), &options) })
+			},
+		}
+	}
+
+	/// The reply telling a trader why their message was refused
+	pub fn reject(reason: RejectReason) -> Value {
+		println!("Rejected order ({}): {}", reason.code(), reason);
+		json!({ "reject": { "code": reason.code(), "reason": reason.to_string() } })
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::exchange::events::ExchangeEvent;
+	use crate::exchange::scheduling::FixedInterval;
+	use crate::exchange::auction::{AuctionResult, ClearingOutcome, Fill};
+	use crate::order::TradeType;
+
+	use std::time::Duration;
+
+	#[test]
+	fn test_handle_messages() {
+		let queue = Arc::new(Queue::new());
+		let (bids, asks) = (Arc::new(Book::new(TradeType::Bid)), Arc::new(Book::new(TradeType::Ask)));
+		let events = Arc::new(EventQueue::new());
+		let scheduler = Scheduler::new(Box::new(FixedInterval::new(3000)), Arc::clone(&queue), Arc::clone(&bids), Arc::clone(&asks));
+		let guard = SelfTradeGuard::new(Arc::clone(&bids), Arc::clone(&asks), Arc::clone(&events));
+		let history = Arc::new(AuctionHistory::new());
+		let ledger = Arc::new(Ledger::new());
+		let gateway = Gateway::new(queue, Arc::new(ExchangeConfig::default()), events, Arc::new(scheduler), Arc::new(guard),
+			Arc::clone(&history), Arc::clone(&ledger), bids, asks);
+
+		let order = json!({"trader_id": "t1", "order_type": "enter", "trade_type": "bid",
+			"p_low": 10.0, "p_high": 20.0, "u_max": 5.0});
+		let reply = gateway.handle(order);
+		assert_eq!(reply["ack"]["trader_id"], "t1");
+		assert_eq!(gateway.queue.len(), 1);
+
+		let reply = gateway.handle(json!({"trader_id": "t1", "order_type": "enter"}));
+		assert_eq!(reply["reject"]["code"], RejectReason::MalformedJson.code());
+
+		// Each trader only receives their own events, and only once
+		for trader_id in ["t1", "t2"] {
+			gateway.events.push(ExchangeEvent::Expired {
+				trader_id: String::from(trader_id),
+				order_id: Some(1),
+				trade_type: TradeType::Bid,
+				timestamp: Duration::from_millis(1000),
+			});
+		}
+		let reply = gateway.handle(json!({"query": "events", "trader_id": "t1"}));
+		assert_eq!(reply["events"].as_array().unwrap().len(), 1);
+		assert_eq!(reply["events"][0]["event"], "expired");
+		assert_eq!(reply["events"][0]["trader_id"], "t1");
+		let reply = gateway.handle(json!({"query": "events", "trader_id": "t1"}));
+		assert!(reply["events"].as_array().unwrap().is_empty());
+		assert_eq!(gateway.events.len(), 1);
+
+		// The schedule shows the policy and when the next batch will have run by
+		let reply = gateway.handle(json!({"query": "schedule"}));
+		assert_eq!(reply["schedule"]["policy"], "fixed interval 3000ms");
+		assert!(reply["schedule"]["boundaries"].as_array().unwrap().is_empty());
+		assert!(reply["schedule"]["next_boundary"].is_object());
+
+		// Recent auctions, oldest first
+		for _ in 0..3 {
+			history.record(&AuctionResult::no_cross(ClearingOutcome::EmptyBids { num_asks: 0 }), 0, 0).unwrap();
+		}
+		let reply = gateway.handle(json!({"query": "history", "since_seq": 1}));
+		assert_eq!(reply["history"].as_array().unwrap().len(), 2);
+		assert_eq!(reply["history"][0]["seq"], 2);
+		let reply = gateway.handle(json!({"query": "history", "last": 1}));
+		assert_eq!(reply["history"][0]["outcome"], "empty_bids");
+		assert_eq!(reply["history"][0]["seq"], 3);
+
+		// Settled accounts and trades
+		let mut result = AuctionResult::no_cross(ClearingOutcome::EmptyBids { num_asks: 0 });
+		result.clearing_price = Some(10.0);
+		result.bid_fills = vec![Fill::of("t1", TradeType::Bid, 2.0)];
+		ledger.settle(&result, 1000);
+		let reply = gateway.handle(json!({"query": "account", "trader_id": "t1"}));
+		assert_eq!(reply["account"]["position"], 2.0);
+		assert_eq!(reply["account"]["cash"], -20.0);
+		let reply = gateway.handle(json!({"query": "account", "trader_id": "t2"}));
+		assert_eq!(reply["account"]["position"], 0.0);
+		let reply = gateway.handle(json!({"query": "trades", "trader_id": "t1"}));
+		assert_eq!(reply["trades"][0]["price"], 10.0);
+		assert_eq!(reply["trades"][0]["quantity"], 2.0);
+
+		// Curves of the empty books, with the grid capped
+		let reply = gateway.handle(json!({"query": "curves"}));
+		assert!(reply["curves"]["points"].as_array().unwrap().is_empty());
+		let reply = gateway.handle(json!({"query": "curves", "sampling": {"type": "grid", "points": 1_000_000}, "range": [0.0, 10.0]}));
+		assert_eq!(reply["curves"]["points"].as_array().unwrap().len(), CURVE_POINTS_LIMIT);
+
+		let reply = gateway.handle(json!({"query": "unknown"}));
+		assert!(reply.get("reject").is_some());
+	}
+}
@@ -0,0 +1,220 @@
+use crate::exchange::auction::AuctionResult;
+use crate::utility::get_time;
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration;
+
+
+/// A summary of a single batch auction kept in the history.
+/// seq: u64 -> sequence number of the auction, starting from 1
+/// timestamp: Duration -> time the auction was recorded
+/// clearing_price: Option<f64> -> market clearing price, None if no cross was found
+/// volume: f64 -> total rate traded in the auction
+/// num_bids: usize -> number of orders in the bids Book when the auction ran
+/// num_asks: usize -> number of orders in the asks Book when the auction ran
+/// outcome: String -> the kind of ClearingOutcome, ie "cross" or "empty_bids"
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuctionRecord {
+	pub seq: u64,
+	pub timestamp: Duration,
+	pub clearing_price: Option<f64>,
+	pub volume: f64,
+	pub num_bids: usize,
+	pub num_asks: usize,
+	pub outcome: String,
+}
+
+/// A threadsafe in-memory history of auction results. If created with a file
+/// each record is also appended to it as a line of JSON, and the history is
+/// reloaded from that file on startup.
+pub struct AuctionHistory {
+	records: Mutex<Vec<AuctionRecord>>,
+	path: Option<PathBuf>,
+}
+
+impl Default for AuctionHistory {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl AuctionHistory {
+	pub fn new() -> AuctionHistory {
+		AuctionHistory {
+			records: Mutex::new(Vec::new()),
+			path: None,
+		}
+	}
+
+	/// Creates a history persisted to the file at 'path', loading any records
+	/// already saved there.
+	pub fn with_file(path: PathBuf) -> io::Result<AuctionHistory> {
+		let mut records = Vec::<AuctionRecord>::new();
+		if path.exists() {
+			let reader = BufReader::new(File::open(&path)?);
+			for line in reader.lines() {
+				let line = line?;
+				if line.trim().is_empty() {
+					continue;
+				}
+				let record = serde_json::from_str(&line)
+					.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+				records.push(record);
+			}
+		}
+
+		Ok(AuctionHistory {
+			records: Mutex::new(records),
+			path: Some(path),
+		})
+	}
+
+	/// Appends the auction result to the history. 'num_bids' and 'num_asks' are
+	/// the depths of the books when the auction ran.
+	pub fn record(&self, result: &AuctionResult, num_bids: usize, num_asks: usize) -> io::Result<AuctionRecord> {
+		let mut records = self.records.lock().unwrap();
+		let record = AuctionRecord {
+			seq: records.last().map_or(1, |r| r.seq + 1),
+			timestamp: get_time(),
+			clearing_price: result.clearing_price,
+			volume: result.volume,
+			num_bids,
+			num_asks,
+			outcome: String::from(result.outcome.kind()),
+		};
+
+		if let Some(path) = &self.path {
+			let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+			let line = serde_json::to_string(&record)
+				.map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+			writeln!(file, "{}", line)?;
+		}
+
+		records.push(record.clone());
+		Ok(record)
+	}
+
+	/// Returns the record with sequence number 'seq'
+	pub fn get(&self, seq: u64) -> Option<AuctionRecord> {
+		let records = self.records.lock().unwrap();
+		records.iter().find(|r| r.seq == seq).cloned()
+	}
+
+	/// Returns the records with sequence numbers in [from, to]
+	pub fn seq_range(&self, from: u64, to: u64) -> Vec<AuctionRecord> {
+		let records = self.records.lock().unwrap();
+		records.iter()
+			.filter(|r| r.seq >= from && r.seq <= to)
+			.cloned()
+			.collect()
+	}
+
+	/// Returns the records with timestamps in [from, to]
+	pub fn time_range(&self, from: Duration, to: Duration) -> Vec<AuctionRecord> {
+		let records = self.records.lock().unwrap();
+		records.iter()
+			.filter(|r| r.timestamp >= from && r.timestamp <= to)
+			.cloned()
+			.collect()
+	}
+
+	/// Returns the most recent 'n' records, oldest first
+	pub fn last_n(&self, n: usize) -> Vec<AuctionRecord> {
+		let records = self.records.lock().unwrap();
+		let start = records.len().saturating_sub(n);
+		records[start..].to_vec()
+	}
+
+	/// Returns the records after sequence number 'since_seq' with timestamps in
+	/// [from, to], each bound only applied when given. At most the 'last' most
+	/// recent matching records are returned, oldest first.
+	pub fn select(&self, since_seq: Option<u64>, from: Option<Duration>, to: Option<Duration>, last: usize) -> Vec<AuctionRecord> {
+		let records = self.records.lock().unwrap();
+		let mut selected: Vec<AuctionRecord> = records.iter().rev()
+			.filter(|r| since_seq.is_none_or(|s| r.seq > s))
+			.filter(|r| from.is_none_or(|f| r.timestamp >= f) && to.is_none_or(|t| r.timestamp <= t))
+			.take(last)
+			.cloned()
+			.collect();
+		selected.reverse();
+		selected
+	}
+
+	/// Utility to see how many auctions have been recorded
+	pub fn len(&self) -> usize {
+		self.records.lock().unwrap().len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::exchange::auction::ClearingOutcome;
+	use std::env;
+	use std::fs;
+
+	fn crossed(price: f64) -> AuctionResult {
+		let mut result = AuctionResult::no_cross(ClearingOutcome::Cross { price, demand: 1.0, supply: 1.0, iterations: 1 });
+		result.clearing_price = Some(price);
+		result.volume = 1.0;
+		result
+	}
+
+	#[test]
+	fn test_record_and_query() {
+		let history = AuctionHistory::new();
+		let start = get_time();
+		for i in 0..5 {
+			history.record(&crossed(i as f64), i, 10 - i).unwrap();
+		}
+		history.record(&AuctionResult::no_cross(ClearingOutcome::EmptyBids { num_asks: 3 }), 0, 3).unwrap();
+
+		assert_eq!(history.len(), 6);
+		assert_eq!(history.get(1).unwrap().clearing_price, Some(0.0));
+		assert_eq!(history.get(3).unwrap().num_asks, 8);
+		assert_eq!(history.get(7), None);
+
+		let last = history.last_n(2);
+		assert_eq!(last.len(), 2);
+		assert_eq!(last[0].seq, 5);
+		assert_eq!(last[1].outcome, "empty_bids");
+		assert_eq!(last[1].clearing_price, None);
+		assert_eq!(history.last_n(100).len(), 6);
+
+		assert_eq!(history.seq_range(2, 4).len(), 3);
+		let seqs = |records: Vec<AuctionRecord>| records.iter().map(|r| r.seq).collect::<Vec<u64>>();
+		assert_eq!(seqs(history.select(Some(3), None, None, 10)), vec![4, 5, 6]);
+		assert_eq!(seqs(history.select(Some(3), None, None, 2)), vec![5, 6]);
+		assert!(history.select(None, Some(get_time() + Duration::from_secs(60)), None, 10).is_empty());
+		assert_eq!(history.time_range(start, get_time()).len(), 6);
+		assert!(history.time_range(Duration::from_secs(0), Duration::from_secs(1)).is_empty());
+	}
+
+	#[test]
+	fn test_persisted_history() {
+		let path = env::temp_dir().join(format!("flow_rs_history_{:?}.jsonl", get_time()));
+
+		{
+			let history = AuctionHistory::with_file(path.clone()).unwrap();
+			history.record(&crossed(10.0), 1, 1).unwrap();
+			history.record(&crossed(11.0), 1, 1).unwrap();
+		}
+
+		// A new history on the same file picks up where the last left off
+		let history = AuctionHistory::with_file(path.clone()).unwrap();
+		assert_eq!(history.len(), 2);
+		assert_eq!(history.record(&crossed(12.0), 1, 1).unwrap().seq, 3);
+		assert_eq!(history.get(2).unwrap().clearing_price, Some(11.0));
+
+		fs::remove_file(path).unwrap();
+	}
+}
@@ -1,5 +1,15 @@
 pub mod order_book;
+pub mod order_index;
 pub mod auction;
 pub mod order_processing;
+pub mod gateway;
 pub mod queue;
 pub mod queue_processing;
+pub mod settlement;
+pub mod events;
+pub mod config;
+pub mod history;
+pub mod scheduling;
+pub mod validation;
+pub mod audit;
+pub mod self_trade;
@@ -1,59 +1,159 @@
-use core::f64::MAX;
 use crate::order::{Order, TradeType};
+use crate::exchange::auction::Fill;
+use crate::exchange::events::ExchangeEvent;
+use crate::exchange::order_index::OrderIndex;
+use crate::exchange::audit::{self, AuditReport};
+use crate::utility::get_time;
 
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
 use std::io;
+use std::time::Duration;
 
 pub fn test_order_book_mod() {
 	println!("Hello, order_book!");
 }
 
+/// An immutable view of a Book's orders and price bounds at one version. Readers
+/// such as the auction can evaluate a snapshot without blocking writers to the Book.
+/// version: u64 -> the Book's version when the snapshot was taken, higher is newer
+/// book_type: TradeType{Bid, Ask} -> the type of the Book
+/// orders: Vec<Order> -> the Book's orders sorted by p_high
+/// min_price: Option<f64> -> lowest p_low of the orders, None if there are none
+/// max_price: Option<f64> -> highest p_high of the orders, None if there are none
+#[derive(Debug, Clone, PartialEq)]
+pub struct BookSnapshot {
+	pub version: u64,
+	pub book_type: TradeType,
+	pub orders: Vec<Order>,
+	pub min_price: Option<f64>,
+	pub max_price: Option<f64>,
+}
+
+impl BookSnapshot {
+	pub fn len(&self) -> usize {
+		self.orders.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.orders.is_empty()
+	}
+
+	/// The exchange assigned ids of the orders in the snapshot
+	pub fn order_ids(&self) -> Vec<u64> {
+		self.orders.iter().filter_map(|o| o.order_id).collect()
+	}
+}
+
 /// The struct for the order books in the exchange. The purpose
 /// is to keep track of bids and asks for calculating the aggregate
 /// supply and demand to find the market clearing price. 
 /// book_type: TradeType{Bid, Ask} -> To differentiate the two order books
-/// orders: Mutex<Vec<Order>> -> Threadsafe vector to keep track of orders
-/// min_price: Mutex<f64> -> Threadsafe minimum market price for computing clearing price
-/// max_price: Mutex<f64> -> Threadsafe maximum market price for computing clearing price
+/// orders: Mutex<OrderIndex> -> Threadsafe orders sorted by p_high and indexed by id, trader and price
+/// changes: Mutex<usize> -> Threadsafe count of orders entered, updated or cancelled
+/// snapshot: Mutex<Option<Arc<BookSnapshot>>> -> the latest snapshot, reused until the orders change
 pub struct Book {
 	pub book_type: TradeType,
-	pub orders: Mutex<Vec<Order>>,
-	pub min_price: Mutex<f64>,
-	pub max_price: Mutex<f64>,
+	pub orders: Mutex<OrderIndex>,
+	changes: Mutex<usize>,
+	snapshot: Mutex<Option<Arc<BookSnapshot>>>,
 }
 
 impl Book {
     pub fn new(book_type: TradeType) -> Book {
     	Book {
     		book_type,
-    		orders: Mutex::new(Vec::<Order>::new()),
-    		min_price: Mutex::new(MAX),
-    		max_price: Mutex::new(0.0),
+    		orders: Mutex::new(OrderIndex::new()),
+    		changes: Mutex::new(0),
+    		snapshot: Mutex::new(None),
     	}
     }
 
-    /// Adds a new order to the Book after acquiring a lock, then sorts by p_high
+    /// Returns an immutable snapshot of the current version of the Book. The orders
+    /// are only copied when they have changed since the last snapshot, otherwise the
+    /// same snapshot is shared.
+    pub fn snapshot(&self) -> Arc<BookSnapshot> {
+    	let mut cached = self.snapshot.lock().unwrap();
+    	let orders = self.orders.lock().expect("ERROR: Couldn't lock book to snapshot");
+    	if let Some(snapshot) = cached.as_ref() {
+    		if snapshot.version == orders.version() {
+    			return Arc::clone(snapshot);
+    		}
+    	}
+
+    	let snapshot = Arc::new(BookSnapshot {
+    		version: orders.version(),
+    		book_type: self.book_type.clone(),
+    		orders: orders.iter().cloned().collect(),
+    		min_price: orders.min_price(),
+    		max_price: orders.max_price(),
+    	});
+    	*cached = Some(Arc::clone(&snapshot));
+    	snapshot
+    }
+
+    /// Checks the Book's invariants: orders sorted by p_high, unique order_ids, indexes
+    /// and min/max prices matching the orders, and valid fields on every order. Every
+    /// discrepancy found is returned in the report rather than panicking.
+    pub fn audit(&self) -> AuditReport {
+    	let orders = self.orders.lock().expect("ERROR: Couldn't lock book to audit");
+    	let mut discrepancies = audit::check_sorted_and_bounds(orders.iter(), orders.min_price(), orders.max_price());
+    	discrepancies.extend(orders.audit());
+    	for order in orders.iter() {
+    		discrepancies.extend(audit::check_order(&self.book_type, order));
+    	}
+
+    	AuditReport {
+    		book_type: self.book_type.clone(),
+    		version: orders.version(),
+    		num_orders: orders.len(),
+    		discrepancies,
+    	}
+    }
+
+    /// The Book's current version, which increases with every change to its orders
+    pub fn version(&self) -> u64 {
+    	self.orders.lock().unwrap().version()
+    }
+
+    /// Adds a new order to the Book after acquiring a lock, the index keeps it sorted by p_high
     pub fn add_order(&self, order: Order) -> io::Result<()> {
     	let mut orders = self.orders.lock().expect("ERROR: Couldn't lock book to update order");
-    	orders.push(order);
-    	orders.sort_by(|a, b| a.p_high.partial_cmp(&b.p_high).unwrap());
+    	orders.insert(order);
+    	self.count_change();
     	Ok(())
     }
 
-    /// Replaces the order in the order book with the supplied 'order' of the same trader_id
-    pub fn update_order(&self, order: Order) -> Result<(), &'static str> {
+    // Finds the key of the order that 'order' targets. Orders with an order_id
+    // target that order, orders without one target the trader's only order.
+    fn target_key(orders: &OrderIndex, order: &Order) -> Option<u64> {
+    	if let Some(id) = order.order_id {
+    		return orders.find(Some(id), &order.trader_id);
+    	}
+    	let keys = orders.trader_keys(&order.trader_id);
+    	if keys.len() > 1 {
+    		println!("ERROR: trader {:?} has several orders, an order_id is required", &order.trader_id);
+    		return None;
+    	}
+    	keys.first().copied()
+    }
+
+    /// Replaces the order in the order book targeted by the supplied 'order', which
+    /// keeps the order_id of the order it replaces along with what it has traded, its
+    /// sequence number and the auctions counted towards its time in force
+    pub fn update_order(&self, mut order: Order) -> Result<(), &'static str> {
     	// Acquire the lock
         let mut orders = self.orders.lock().expect("ERROR: Couldn't lock book to update order");
-        // Search for existing order's index
-        let order_index = orders.iter().position(|o| o.trader_id == order.trader_id);
-
-        if let Some(i) = order_index {
-        	// Add new order to end of the vector
-        	orders.push(order);
-    		// Swap orders then pop off the old order that is now at the end of vector
-        	let last = orders.len() - 1;
-        	orders.swap(i, last);
-        	orders.pop();
+        // Search for existing order's key
+        let order_key = Book::target_key(&orders, &order);
+
+        if let Some(key) = order_key {
+        	if let Some(old) = orders.get(key) {
+        		order.order_id = old.order_id;
+        		order.carry_over(old, get_time());
+        	}
+        	// Re-index the order under its new prices
+        	orders.replace(key, order);
+        	self.count_change();
         } else {
         	println!("ERROR: order not found to update: {:?}", &order.trader_id);
         	return Err("ERROR: order not found to update");
@@ -66,11 +166,12 @@ impl Book {
     pub fn cancel_order(&self, order: Order) -> Result<(), &'static str> {
     	// Acquire the lock
         let mut orders = self.orders.lock().expect("couldn't acquire lock cancelling order");
-        // Search for existing order's index
-        let order_index: Option<usize> = orders.iter().position(|o| &o.trader_id == &order.trader_id);
+        // Search for existing order's key
+        let order_key = Book::target_key(&orders, &order);
 
-        if let Some(i) = order_index {
-        	orders.remove(i);
+        if let Some(key) = order_key {
+        	orders.remove(key);
+        	self.count_change();
         } else {
         	println!("ERROR: order not found to cancel: {:?}", &order.trader_id);
         	return Err("ERROR: order not found to cancel");
@@ -79,71 +180,187 @@ impl Book {
         Ok(())
     }
 
-    pub fn peek_id_pos(&self, trader_id: String) -> Option<usize> {
-    	// Acquire the lock
-        let orders = self.orders.lock().unwrap();
-        // Search for existing order's index
-        orders.iter().position(|o| o.trader_id == trader_id)
+    /// Caps each fill so an order with a total quantity never trades more than it
+    /// has remaining over a batch of 'duration' milliseconds.
+    pub fn cap_fills(&self, fills: &mut [Fill], duration: u64) {
+    	let secs = duration as f64 / 1000.0;
+    	let orders = self.orders.lock().expect("ERROR: Couldn't lock book to cap fills");
+
+    	for fill in fills.iter_mut() {
+    		let remaining = orders.find(fill.order_id, &fill.trader_id)
+    			.and_then(|key| orders.get(key))
+    			.and_then(|o| o.remaining);
+    		if let Some(r) = remaining {
+    			if fill.rate * secs > r {
+    				fill.rate = r / secs;
+    			}
+    		}
+    	}
     }
 
-    /// Utility to see depth of order book
-    pub fn len(&self) -> usize {
-    	let orders = self.orders.lock().unwrap();
-    	orders.len()
+    /// Decrements the remaining quantity of every order with a total quantity by its
+    /// fill over a batch of 'duration' milliseconds. Fills should first be capped with
+    /// cap_fills. Exhausted orders are removed from the Book and a completion event is
+    /// returned for each.
+    pub fn deplete(&self, fills: &[Fill], duration: u64) -> Vec<ExchangeEvent> {
+    	let secs = duration as f64 / 1000.0;
+    	let mut events = Vec::<ExchangeEvent>::new();
+    	{
+	    	let mut orders = self.orders.lock().expect("ERROR: Couldn't lock book to deplete orders");
+
+	    	let mut exhausted = Vec::<u64>::new();
+	    	for fill in fills.iter() {
+	    		let key = match orders.find(fill.order_id, &fill.trader_id) {
+	    			Some(key) => key,
+	    			None => continue,
+	    		};
+	    		if orders.get_mut(key).is_some_and(|o| o.deplete(fill.rate * secs)) {
+	    			exhausted.push(key);
+	    		}
+	    	}
+
+	    	for order in exhausted.into_iter().filter_map(|key| orders.remove(key)) {
+	    		events.push(ExchangeEvent::Completed {
+	    			trader_id: order.trader_id,
+	    			order_id: order.order_id,
+	    			trade_type: order.trade_type,
+	    			quantity: order.quantity.unwrap_or(0.0),
+	    			timestamp: get_time(),
+	    		});
+	    	}
+    	}
+    	events
     }
 
-    /// Atomically updates the Book's max price
-    pub fn update_max_price(&self, p_high: &f64) {
-		let mut max_price = self.max_price.lock().unwrap();
-		if *p_high > *max_price {
-			*max_price = *p_high;
-		} 
+    /// Counts the auction that just ran towards the time in force of the orders with
+    /// 'order_ids', the orders in the snapshot it cleared. Orders that arrived while it
+    /// ran didn't take part, so they aren't counted. Only orders whose time in force
+    /// counts auctions are changed, so the Book's version is left alone without them.
+    pub fn count_auction(&self, order_ids: &[u64]) {
+    	let mut orders = self.orders.lock().expect("ERROR: Couldn't lock book to count auction");
+    	let keys: Vec<u64> = order_ids.iter()
+    		.filter_map(|id| orders.key_of(*id))
+    		.filter(|key| orders.get(*key).is_some_and(|o| o.counts_auctions()))
+    		.collect();
+    	for key in keys.into_iter() {
+    		if let Some(order) = orders.get_mut(key) {
+    			order.auctions += 1;
+    		}
+    	}
     }
 
-    /// Atomically updates the Book's min price
-	pub fn update_min_price(&self, p_low: &f64) {
-		let mut min_price = self.min_price.lock().unwrap();
-		if *p_low < *min_price {
-			*min_price = *p_low;
-		} 
+    /// Removes every order whose time in force has run out at time 'now' and returns
+    /// an expiry event for each. Called by the exchange at the batch boundary.
+    pub fn expire(&self, now: Duration) -> Vec<ExchangeEvent> {
+    	let expired: Vec<Order> = {
+	    	let mut orders = self.orders.lock().expect("ERROR: Couldn't lock book to expire orders");
+	    	orders.remove_where(|o| o.is_expired(now))
+    	};
+
+    	expired.into_iter()
+    		.map(|o| ExchangeEvent::Expired {
+    			trader_id: o.trader_id,
+    			order_id: o.order_id,
+    			trade_type: o.trade_type,
+    			timestamp: now,
+    		})
+    		.collect()
     }
 
-    /// Returns the Book's min price
-    pub fn get_min_price(&self) -> f64 {
-    	let price = self.min_price.lock().unwrap();
-    	price.clone() as f64
+    /// Paces every deadline order after the auction at time 'now', where 'batch' is the
+    /// expected length of the next batch in milliseconds. Orders whose deadline has
+    /// arrived are removed and report their shortfall, the rest have their u_max
+    /// rewritten to spread their remaining quantity until the deadline and report progress.
+    pub fn pace(&self, now: Duration, batch: u64) -> Vec<ExchangeEvent> {
+    	let now_ms = now.as_millis() as u64;
+    	let mut events = Vec::<ExchangeEvent>::new();
+    	let reached: Vec<Order> = {
+	    	let mut orders = self.orders.lock().expect("ERROR: Couldn't lock book to pace orders");
+	    	let reached = orders.remove_where(|o| o.deadline.is_some_and(|d| now_ms >= d));
+
+	    	for order in orders.iter_mut().filter(|o| o.deadline.is_some()) {
+	    		order.pace(now, batch);
+	    		events.push(ExchangeEvent::Progress {
+	    			trader_id: order.trader_id.clone(),
+	    			order_id: order.order_id,
+	    			trade_type: order.trade_type.clone(),
+	    			filled: order.filled(),
+	    			remaining: order.remaining.unwrap_or(0.0),
+	    			u_max: order.u_max,
+	    			timestamp: now,
+	    		});
+	    	}
+	    	reached
+    	};
+
+    	for order in reached {
+    		events.push(ExchangeEvent::DeadlineReached {
+    			filled: order.filled(),
+    			shortfall: order.remaining.unwrap_or(0.0),
+    			trader_id: order.trader_id,
+    			order_id: order.order_id,
+    			trade_type: order.trade_type,
+    			timestamp: now,
+    		});
+    	}
+    	events
     }
 
-    /// Returns the Book's max price
-    pub fn get_max_price(&self) -> f64 {
-    	let price = self.max_price.lock().unwrap();
-    	price.clone() as f64
+    /// Returns a copy of the trader's first order in the Book
+    pub fn peek_trader_order(&self, trader_id: &str) -> Option<Order> {
+        let orders = self.orders.lock().unwrap();
+        let key = *orders.trader_keys(trader_id).first()?;
+        orders.get(key).cloned()
     }
 
-    /// Finds a new maximum Book price in the event that the previous was
-    /// updated or cancelled and updates the Book. Utilizes Book being sorted by p_high
-    pub fn find_new_max(&self) {
-    	// find the order with the max price (from sorted list):
-    	let orders = self.orders.lock().unwrap();
+    /// Returns a copy of the order with the exchange assigned 'order_id'
+    pub fn peek_order(&self, order_id: u64) -> Option<Order> {
+        let orders = self.orders.lock().unwrap();
+        let key = orders.key_of(order_id)?;
+        orders.get(key).cloned()
+    }
 
-    	let new_max = orders.last().unwrap().p_high;
+    /// Returns the order_ids of every order the trader has in the Book
+    pub fn trader_orders(&self, trader_id: &str) -> Vec<u64> {
+        let orders = self.orders.lock().unwrap();
+        orders.trader_keys(trader_id).into_iter()
+        	.filter_map(|key| orders.get(key).and_then(|o| o.order_id))
+        	.collect()
+    }
 
-    	// Update the book with new max price
-    	let mut max_price = self.max_price.lock().unwrap();
-    	*max_price = new_max;
+    // Counts an order being entered, updated or cancelled
+    fn count_change(&self) {
+    	*self.changes.lock().unwrap() += 1;
     }
 
-    /// Finds a new minimum Book price in the event that the previous was
-    /// updated or cancelled and updates the Book.
-    pub fn find_new_min(&self) {
+    /// Total number of orders that have been entered, updated or cancelled
+    pub fn num_changes(&self) -> usize {
+    	*self.changes.lock().unwrap()
+    }
+
+    /// Utility to see depth of order book
+    pub fn len(&self) -> usize {
     	let orders = self.orders.lock().unwrap();
+    	orders.len()
+    }
 
-    	// Iterates over all orders until a minimum is found
-    	let new_min = orders.iter().fold(MAX, |min, order| if order.p_low < min {order.p_low} else {min});
+    /// Utility to see if the order book is empty
+    pub fn is_empty(&self) -> bool {
+    	self.len() == 0
+    }
+
+    /// Returns the Book's min price, the lowest p_low of its orders or f64::MAX if
+    /// the Book is empty. The p_low index keeps this correct after every change.
+    pub fn get_min_price(&self) -> f64 {
+    	let orders = self.orders.lock().unwrap();
+    	orders.min_price().unwrap_or(f64::MAX)
+    }
 
-    	// Update the book with new min price
-    	let mut min_price = self.min_price.lock().unwrap();
-    	*min_price = new_min;
+    /// Returns the Book's max price, the highest p_high of its orders or 0.0 if
+    /// the Book is empty. The p_high index keeps this correct after every change.
+    pub fn get_max_price(&self) -> f64 {
+    	let orders = self.orders.lock().unwrap();
+    	orders.max_price().unwrap_or(0.0)
     }
 }
 
@@ -151,7 +368,9 @@ impl Book {
 #[cfg(test)]
 mod tests {
 	use super::*;
-    use crate::order::{TradeType};
+    use crate::order::{TradeType, OrderType, TimeInForce};
+    use crate::order::schedule::ScheduleSpec;
+    use crate::exchange::audit::Discrepancy;
     use std::sync::Arc;
     use std::thread;
 
@@ -159,8 +378,246 @@ mod tests {
 	fn test_new_book() {
 		let book = Book::new(TradeType::Bid);
 		assert_eq!(book.book_type, TradeType::Bid);
-		assert_eq!(*book.min_price.lock().unwrap(), MAX);
-		assert_eq!(*book.max_price.lock().unwrap(), 0.0);
+		assert_eq!(book.get_min_price(), f64::MAX);
+		assert_eq!(book.get_max_price(), 0.0);
+	}
+
+	#[test]
+	fn test_update_keeps_state() {
+		let book = Book::new(TradeType::Bid);
+		let new_bid = |quantity: f64, time_in_force: TimeInForce| {
+			let mut order = Order::new(String::from("trader"), OrderType::Enter, TradeType::Bid,
+				0.0, 100.0, 10.0, ScheduleSpec::PiecewiseLinear).with_quantity(quantity).with_time_in_force(time_in_force);
+			order.seq = Some(5);
+			order
+		};
+		book.add_order(new_bid(100.0, TimeInForce::ImmediateOrCancel)).unwrap();
+		{
+			let mut orders = book.orders.lock().unwrap();
+			let order = orders.iter_mut().next().unwrap();
+			order.remaining = Some(40.0);
+			order.auctions = 1;
+		}
+
+		// Resending the same order doesn't undo its fills or re-arm its time in force
+		let mut update = new_bid(100.0, TimeInForce::ImmediateOrCancel);
+		update.order_type = OrderType::Update;
+		update.seq = Some(9);
+		book.update_order(update).unwrap();
+		let order = book.peek_trader_order("trader").unwrap();
+		assert_eq!((order.remaining, order.auctions, order.seq), (Some(40.0), 1, Some(9)));
+		assert!(order.is_expired(get_time()));
+
+		// A new quantity and time in force apply on top of what has been traded
+		let mut update = new_bid(150.0, TimeInForce::GoodForAuctions { auctions: 3 });
+		update.order_type = OrderType::Update;
+		book.update_order(update).unwrap();
+		let order = book.peek_trader_order("trader").unwrap();
+		assert_eq!((order.remaining, order.auctions), (Some(90.0), 0));
+
+		// Deadline orders are paced from what remains
+		let deadline = get_time().as_millis() as u64 + 10_000;
+		let mut update = new_bid(150.0, TimeInForce::GoodTillCancel).with_deadline(deadline);
+		update.order_type = OrderType::Update;
+		book.update_order(update).unwrap();
+		let order = book.peek_trader_order("trader").unwrap();
+		assert!(order.u_max >= 9.0 && order.u_max < 9.1);
+	}
+
+	#[test]
+	fn test_deplete_orders() {
+		let book = Book::new(TradeType::Bid);
+		let new_bid = |id: &str| Order::new(String::from(id), OrderType::Enter, TradeType::Bid,
+			0.0, 100.0, 10.0, ScheduleSpec::PiecewiseLinear);
+		book.add_order(new_bid("finite").with_quantity(15.0)).unwrap();
+		book.add_order(new_bid("forever")).unwrap();
+
+		let fill = |id: &str| Fill::of(id, TradeType::Bid, 10.0);
+
+		// First 1s batch trades 10 of the 15 units
+		let mut fills = vec![fill("finite"), fill("forever")];
+		book.cap_fills(&mut fills, 1000);
+		assert_eq!(fills[0].rate, 10.0);
+		assert!(book.deplete(&fills, 1000).is_empty());
+		assert_eq!(book.len(), 2);
+
+		// Second batch is capped at the 5 units that remain
+		let mut fills = vec![fill("finite"), fill("forever")];
+		book.cap_fills(&mut fills, 1000);
+		let events = book.deplete(&fills, 1000);
+		assert_eq!(fills[0].rate, 5.0);
+		assert_eq!(fills[1].rate, 10.0);
+		assert_eq!(events.len(), 1);
+		assert_eq!(events[0].trader_id(), "finite");
+		assert_eq!(book.len(), 1);
+		assert!(book.peek_trader_order("finite").is_none());
+	}
+
+	#[test]
+	fn test_expire_orders() {
+		use crate::order::TimeInForce;
+		let book = Book::new(TradeType::Bid);
+		let new_bid = |id: &str, order_id: u64, tif: TimeInForce| {
+			let mut order = Order::new(String::from(id), OrderType::Enter, TradeType::Bid,
+				0.0, 100.0, 10.0, ScheduleSpec::PiecewiseLinear).with_time_in_force(tif);
+			order.order_id = Some(order_id);
+			order
+		};
+		book.add_order(new_bid("gtc", 1, TimeInForce::GoodTillCancel)).unwrap();
+		book.add_order(new_bid("twice", 2, TimeInForce::GoodForAuctions { auctions: 2 })).unwrap();
+		book.add_order(new_bid("ioc", 3, TimeInForce::ImmediateOrCancel)).unwrap();
+		book.add_order(new_bid("gtt", 4, TimeInForce::GoodTillTime { expires: 5000 })).unwrap();
+
+		// Nothing expires before the first auction
+		let now = Duration::from_millis(1000);
+		assert!(book.expire(now).is_empty());
+
+		// An order that arrives while the auction runs isn't counted by it
+		let ids = book.snapshot().order_ids();
+		book.add_order(new_bid("late", 5, TimeInForce::ImmediateOrCancel)).unwrap();
+		book.count_auction(&ids);
+		let events = book.expire(now);
+		assert_eq!(events.len(), 1);
+		assert_eq!(events[0].trader_id(), "ioc");
+		assert!(book.peek_trader_order("late").is_some());
+
+		// Counting orders that don't count auctions doesn't change the Book
+		let version = book.version();
+		book.count_auction(&[1, 4]);
+		assert_eq!(book.version(), version);
+
+		book.count_auction(&[1, 2, 4]);
+		assert!(book.version() > version);
+		let events = book.expire(Duration::from_millis(5000));
+		let mut expired: Vec<&str> = events.iter().map(|e| e.trader_id()).collect();
+		expired.sort_unstable();
+		assert_eq!(expired, vec!["gtt", "twice"]);
+		assert_eq!(book.len(), 2);
+		assert!(book.peek_trader_order("gtc").is_some());
+	}
+
+	#[test]
+	fn test_pace_deadline_orders() {
+		let book = Book::new(TradeType::Bid);
+		let new_bid = |id: &str| Order::new(String::from(id), OrderType::Enter, TradeType::Bid,
+			0.0, 100.0, 10.0, ScheduleSpec::PiecewiseLinear);
+		book.add_order(new_bid("gtc")).unwrap();
+		book.add_order(new_bid("deadline").with_quantity(100.0).with_deadline(10_000)).unwrap();
+		let u_max = |book: &Book| book.peek_trader_order("deadline").unwrap().u_max;
+
+		// 100 units over 10 seconds
+		let events = book.pace(Duration::from_millis(0), 1000);
+		assert_eq!(events.len(), 1);
+		assert_eq!(u_max(&book), 10.0);
+
+		// After trading 10 units the remaining 90 are spread over 9 seconds
+		let fill = Fill::of("deadline", TradeType::Bid, 10.0);
+		book.deplete(&[fill], 1000);
+		match &book.pace(Duration::from_millis(1000), 1000)[0] {
+			ExchangeEvent::Progress { filled, remaining, u_max, .. } => {
+				assert_eq!((*filled, *remaining, *u_max), (10.0, 90.0, 10.0));
+			},
+			other => panic!("Expected progress, got {:?}", other),
+		}
+
+		// With less than a batch to go everything left is asked for in one batch
+		book.pace(Duration::from_millis(9500), 1000);
+		assert_eq!(u_max(&book), 90.0);
+
+		// The deadline removes the order and reports what it didn't trade
+		match &book.pace(Duration::from_millis(10_000), 1000)[0] {
+			ExchangeEvent::DeadlineReached { filled, shortfall, .. } => {
+				assert_eq!((*filled, *shortfall), (10.0, 90.0));
+			},
+			other => panic!("Expected deadline, got {:?}", other),
+		}
+		assert_eq!(book.len(), 1);
+		assert_eq!(book.peek_trader_order("gtc").unwrap().u_max, 10.0);
+	}
+
+	#[test]
+	fn test_price_bounds() {
+		let book = Book::new(TradeType::Bid);
+		let new_bid = |id: &str, ot: OrderType, p_low: f64, p_high: f64| Order::new(String::from(id), ot,
+			TradeType::Bid, p_low, p_high, 10.0, ScheduleSpec::PiecewiseLinear);
+		book.add_order(new_bid("zero", OrderType::Enter, 0.0, 10.0)).unwrap();
+		book.add_order(new_bid("wide", OrderType::Enter, 5.0, 90.0)).unwrap();
+		book.add_order(new_bid("same", OrderType::Enter, 5.0, 90.0)).unwrap();
+		assert_eq!((book.get_min_price(), book.get_max_price()), (0.0, 90.0));
+
+		// Updating the order with the min price away from it, including a p_low of 0.0
+		book.update_order(new_bid("zero", OrderType::Update, 20.0, 30.0)).unwrap();
+		assert_eq!((book.get_min_price(), book.get_max_price()), (5.0, 90.0));
+
+		// A shared max survives cancelling one of the orders at it
+		book.cancel_order(new_bid("wide", OrderType::Cancel, 5.0, 90.0)).unwrap();
+		assert_eq!((book.get_min_price(), book.get_max_price()), (5.0, 90.0));
+		book.cancel_order(new_bid("same", OrderType::Cancel, 5.0, 90.0)).unwrap();
+		assert_eq!((book.get_min_price(), book.get_max_price()), (20.0, 30.0));
+
+		// Emptying the book resets the bounds rather than panicking
+		book.cancel_order(new_bid("zero", OrderType::Cancel, 20.0, 30.0)).unwrap();
+		assert_eq!((book.get_min_price(), book.get_max_price()), (f64::MAX, 0.0));
+	}
+
+	#[test]
+	fn test_book_snapshot() {
+		let book = Book::new(TradeType::Ask);
+		let new_ask = |id: &str, p_low: f64, p_high: f64| Order::new(String::from(id), OrderType::Enter,
+			TradeType::Ask, p_low, p_high, 10.0, ScheduleSpec::PiecewiseLinear);
+		let empty = book.snapshot();
+		assert!(empty.is_empty());
+		assert_eq!((empty.min_price, empty.max_price), (None, None));
+
+		book.add_order(new_ask("a", 10.0, 20.0)).unwrap();
+		book.add_order(new_ask("b", 5.0, 15.0)).unwrap();
+		let first = book.snapshot();
+		assert!(first.version > empty.version);
+		assert_eq!(first.len(), 2);
+		assert_eq!((first.min_price, first.max_price), (Some(5.0), Some(20.0)));
+
+		// Unchanged books share their snapshot
+		assert!(Arc::ptr_eq(&first, &book.snapshot()));
+
+		// Later writes make a new version and leave earlier snapshots untouched
+		book.cancel_order(new_ask("a", 10.0, 20.0)).unwrap();
+		let second = book.snapshot();
+		assert!(second.version > first.version);
+		assert_eq!(second.version, book.version());
+		assert_eq!(second.len(), 1);
+		assert_eq!(first.len(), 2);
+		assert_eq!(first.orders[1].trader_id, "a");
+	}
+
+	#[test]
+	fn test_audit_book() {
+		let book = Book::new(TradeType::Bid);
+		let new_bid = |id: &str, p_high: f64| Order::new(String::from(id), OrderType::Enter, TradeType::Bid,
+			0.0, p_high, 10.0, ScheduleSpec::PiecewiseLinear);
+		book.add_order(new_bid("a", 10.0)).unwrap();
+		book.add_order(new_bid("b", 20.0)).unwrap();
+		let report = book.audit();
+		assert!(report.is_ok());
+		assert_eq!(report.num_orders, 2);
+
+		// Change an indexed price and a remaining quantity behind the Book's back
+		for order in book.orders.lock().unwrap().iter_mut() {
+			if order.trader_id == "a" {
+				order.p_high = 30.0;
+				order.remaining = Some(5.0);
+			}
+		}
+		let report = book.audit();
+		assert!(!report.is_ok());
+		let kinds: Vec<&str> = report.discrepancies.iter().map(|d| match d {
+			Discrepancy::Unsorted { .. } => "unsorted",
+			Discrepancy::BoundsMismatch { .. } => "bounds",
+			Discrepancy::IndexMismatch { .. } => "index",
+			Discrepancy::InvalidOrder { .. } => "order",
+			Discrepancy::DuplicateId { .. } => "duplicate",
+		}).collect();
+		assert_eq!(kinds, vec!["unsorted", "bounds", "index", "order"]);
+		assert!(report.to_string().contains("2 orders: 4 discrepancies"));
 	}
 
 	#[test]
@@ -176,10 +633,10 @@ mod tests {
 
 				let handle = thread::spawn(move || {
 					// Acquire lock and update book in separate thread
-					let mut max_price = book.max_price.lock().unwrap();
-					// dereference the mutex to modify
-					*max_price += 5.0;
-					// assert_eq!(*max_price, 5.0);
+					let mut orders = book.orders.lock().unwrap();
+					let p_high = 5.0 * (orders.len() + 1) as f64;
+					orders.insert(Order::new(String::from("trader"), OrderType::Enter, TradeType::Bid,
+						0.0, p_high, 10.0, ScheduleSpec::PiecewiseLinear));
 				});
 				handles.push(handle);
 			}
@@ -190,7 +647,7 @@ mod tests {
 			handle.join().unwrap();
 		}
 
-		assert_eq!(*book.max_price.lock().unwrap(), 50.0);
+		assert_eq!(book.get_max_price(), 50.0);
 
 	}
 }
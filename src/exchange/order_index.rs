@@ -0,0 +1,290 @@
+use crate::order::Order;
+use crate::exchange::audit::Discrepancy;
+
+use rayon::prelude::*;
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+
+/// A price that can be used as a key in the ordered price indexes. Prices are
+/// compared with total_cmp so NaN can't break the ordering.
+#[derive(Debug, Clone, Copy)]
+pub struct Price(pub f64);
+
+impl PartialEq for Price {
+	fn eq(&self, other: &Price) -> bool {
+		self.cmp(other) == Ordering::Equal
+	}
+}
+
+impl Eq for Price {}
+
+impl PartialOrd for Price {
+	fn partial_cmp(&self, other: &Price) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+impl Ord for Price {
+	fn cmp(&self, other: &Price) -> Ordering {
+		self.0.total_cmp(&other.0)
+	}
+}
+
+/// The orders in a Book, indexed so that every lookup and mutation is logarithmic.
+/// Each order is given a key when it enters the index that it keeps until removed.
+/// orders: BTreeMap<(Price, u64), Order> -> orders sorted by p_high, then by key
+/// highs: HashMap<u64, Price> -> key to p_high, to locate an order in 'orders'
+/// lows: BTreeSet<(Price, u64)> -> ordered index of p_low
+/// ids: HashMap<u64, u64> -> exchange assigned order_id to key
+/// traders: HashMap<String, BTreeSet<u64>> -> trader_id to the keys of their orders
+/// next_key: u64 -> key given to the next order inserted
+/// version: u64 -> incremented by every change to the orders
+#[derive(Debug, Clone, Default)]
+pub struct OrderIndex {
+	orders: BTreeMap<(Price, u64), Order>,
+	highs: HashMap<u64, Price>,
+	lows: BTreeSet<(Price, u64)>,
+	ids: HashMap<u64, u64>,
+	traders: HashMap<String, BTreeSet<u64>>,
+	next_key: u64,
+	version: u64,
+}
+
+impl OrderIndex {
+	pub fn new() -> OrderIndex {
+		OrderIndex::default()
+	}
+
+	pub fn len(&self) -> usize {
+		self.orders.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.orders.is_empty()
+	}
+
+	/// The number of changes made to the orders, used to version snapshots
+	pub fn version(&self) -> u64 {
+		self.version
+	}
+
+	/// Iterates over the orders in order of p_high
+	pub fn iter(&self) -> impl Iterator<Item = &Order> {
+		self.orders.values()
+	}
+
+	/// Iterates over the orders in parallel
+	pub fn par_iter(&self) -> impl ParallelIterator<Item = &Order> {
+		self.orders.par_iter().map(|(_, order)| order)
+	}
+
+	/// The lowest p_low of any order, None if the index is empty
+	pub fn min_price(&self) -> Option<f64> {
+		self.lows.iter().next().map(|(p, _)| p.0)
+	}
+
+	/// The highest p_high of any order, None if the index is empty
+	pub fn max_price(&self) -> Option<f64> {
+		self.orders.keys().next_back().map(|(p, _)| p.0)
+	}
+
+	pub fn get(&self, key: u64) -> Option<&Order> {
+		let high = self.highs.get(&key)?;
+		self.orders.get(&(*high, key))
+	}
+
+	/// Mutable access to an order. Its prices, order_id and trader_id are indexed
+	/// and must not be changed through the reference, use replace instead.
+	pub(crate) fn get_mut(&mut self, key: u64) -> Option<&mut Order> {
+		let high = self.highs.get(&key)?;
+		self.version += 1;
+		self.orders.get_mut(&(*high, key))
+	}
+
+	/// Mutable access to every order, with the same restrictions as get_mut
+	pub(crate) fn iter_mut(&mut self) -> impl Iterator<Item = &mut Order> {
+		self.version += 1;
+		self.orders.values_mut()
+	}
+
+	/// Adds an order to the index and returns its key
+	pub fn insert(&mut self, order: Order) -> u64 {
+		let key = self.next_key;
+		self.next_key += 1;
+		self.insert_at(key, order);
+		key
+	}
+
+	// Indexes 'order' under 'key'
+	fn insert_at(&mut self, key: u64, order: Order) {
+		self.version += 1;
+		let high = Price(order.p_high);
+		self.highs.insert(key, high);
+		self.lows.insert((Price(order.p_low), key));
+		if let Some(id) = order.order_id {
+			self.ids.insert(id, key);
+		}
+		self.traders.entry(order.trader_id.clone()).or_default().insert(key);
+		self.orders.insert((high, key), order);
+	}
+
+	/// Removes the order with 'key' from every index and returns it
+	pub fn remove(&mut self, key: u64) -> Option<Order> {
+		let high = self.highs.remove(&key)?;
+		let order = self.orders.remove(&(high, key))?;
+		self.version += 1;
+		self.lows.remove(&(Price(order.p_low), key));
+		if let Some(id) = order.order_id {
+			self.ids.remove(&id);
+		}
+		if let Some(keys) = self.traders.get_mut(&order.trader_id) {
+			keys.remove(&key);
+			if keys.is_empty() {
+				self.traders.remove(&order.trader_id);
+			}
+		}
+		Some(order)
+	}
+
+	/// Swaps the order with 'key' for 'order', which keeps the key and so its place
+	/// among orders with the same p_high. Returns the order that was replaced.
+	pub fn replace(&mut self, key: u64, order: Order) -> Option<Order> {
+		let old = self.remove(key)?;
+		self.insert_at(key, order);
+		Some(old)
+	}
+
+	/// Removes every order that 'f' returns true for and returns them
+	pub fn remove_where<F: FnMut(&Order) -> bool>(&mut self, mut f: F) -> Vec<Order> {
+		let keys: Vec<u64> = self.orders.iter()
+			.filter(|(_, o)| f(o))
+			.map(|((_, key), _)| *key)
+			.collect();
+		keys.into_iter().filter_map(|key| self.remove(key)).collect()
+	}
+
+	/// The key of the order with the exchange assigned 'order_id'
+	pub fn key_of(&self, order_id: u64) -> Option<u64> {
+		self.ids.get(&order_id).copied()
+	}
+
+	/// The keys of every order the trader has in the index
+	pub fn trader_keys(&self, trader_id: &str) -> Vec<u64> {
+		match self.traders.get(trader_id) {
+			Some(keys) => keys.iter().copied().collect(),
+			None => Vec::new(),
+		}
+	}
+
+	/// The key of the order identified by 'order_id' and 'trader_id'. Without an
+	/// order_id this is the trader's order that also has no order_id.
+	pub fn find(&self, order_id: Option<u64>, trader_id: &str) -> Option<u64> {
+		match order_id {
+			Some(id) => self.key_of(id).filter(|k| self.get(*k).is_some_and(|o| o.trader_id == trader_id)),
+			None => self.traders.get(trader_id)?.iter().copied()
+				.find(|k| self.get(*k).is_some_and(|o| o.order_id.is_none())),
+		}
+	}
+
+	/// Checks every index agrees with the orders and that no order_id is used twice
+	pub fn audit(&self) -> Vec<Discrepancy> {
+		let mut discrepancies = Vec::<Discrepancy>::new();
+		let mut mismatch = |index: &'static str, detail: String| {
+			discrepancies.push(Discrepancy::IndexMismatch { index, detail });
+		};
+
+		let mut id_counts = HashMap::<u64, usize>::new();
+		for ((high, key), order) in self.orders.iter() {
+			if high.0.total_cmp(&order.p_high) != Ordering::Equal || self.highs.get(key) != Some(high) {
+				mismatch("p_high", format!("key {} is indexed at {} but has p_high {}", key, high.0, order.p_high));
+			}
+			if !self.lows.contains(&(Price(order.p_low), *key)) {
+				mismatch("p_low", format!("key {} with p_low {} is missing", key, order.p_low));
+			}
+			if let Some(id) = order.order_id {
+				*id_counts.entry(id).or_insert(0) += 1;
+				if self.ids.get(&id) != Some(key) {
+					mismatch("order_id", format!("order_id {} doesn't point to key {}", id, key));
+				}
+			}
+			if !self.traders.get(&order.trader_id).is_some_and(|keys| keys.contains(key)) {
+				mismatch("trader_id", format!("key {} is missing from trader {:?}", key, order.trader_id));
+			}
+		}
+
+		// Entries left over from orders that are no longer in the index
+		let traders: usize = self.traders.values().map(|keys| keys.len()).sum();
+		let with_ids = self.orders.values().filter(|o| o.order_id.is_some()).count();
+		for (index, len, expected) in [("p_high", self.highs.len(), self.len()), ("p_low", self.lows.len(), self.len()),
+			("order_id", self.ids.len(), with_ids), ("trader_id", traders, self.len())] {
+			if len > expected {
+				mismatch(index, format!("{} entries for {} orders", len, expected));
+			}
+		}
+
+		let mut duplicates: Vec<(u64, usize)> = id_counts.into_iter().filter(|(_, count)| *count > 1).collect();
+		duplicates.sort_unstable();
+		discrepancies.extend(duplicates.into_iter()
+			.map(|(order_id, count)| Discrepancy::DuplicateId { order_id, count }));
+		discrepancies
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::order::{OrderType, TradeType};
+	use crate::order::schedule::ScheduleSpec;
+
+	fn new_order(id: &str, order_id: Option<u64>, p_low: f64, p_high: f64) -> Order {
+		let mut order = Order::new(String::from(id), OrderType::Enter, TradeType::Bid,
+			p_low, p_high, 10.0, ScheduleSpec::PiecewiseLinear);
+		order.order_id = order_id;
+		order
+	}
+
+	#[test]
+	fn test_order_index() {
+		let mut index = OrderIndex::new();
+		assert_eq!((index.min_price(), index.max_price()), (None, None));
+
+		let a = index.insert(new_order("a", Some(1), 5.0, 30.0));
+		let b = index.insert(new_order("b", Some(2), 1.0, 10.0));
+		let c = index.insert(new_order("b", None, 3.0, 20.0));
+		assert_eq!(index.len(), 3);
+		assert_eq!((index.min_price(), index.max_price()), (Some(1.0), Some(30.0)));
+
+		// Orders stay sorted by p_high
+		let highs: Vec<f64> = index.iter().map(|o| o.p_high).collect();
+		assert_eq!(highs, vec![10.0, 20.0, 30.0]);
+
+		// Lookups by order_id and by trader
+		assert_eq!(index.key_of(2), Some(b));
+		assert_eq!(index.find(Some(1), "a"), Some(a));
+		assert_eq!(index.find(Some(1), "b"), None);
+		assert_eq!(index.find(None, "b"), Some(c));
+		assert_eq!(index.trader_keys("b"), vec![b, c]);
+
+		// Replacing an order moves it in the price indexes and keeps its key
+		index.replace(a, new_order("a", Some(1), 0.5, 15.0));
+		let highs: Vec<f64> = index.iter().map(|o| o.p_high).collect();
+		assert_eq!(highs, vec![10.0, 15.0, 20.0]);
+		assert_eq!((index.min_price(), index.max_price()), (Some(0.5), Some(20.0)));
+		assert_eq!(index.get(a).unwrap().p_low, 0.5);
+
+		// Removing cleans up every index
+		assert_eq!(index.remove(c).unwrap().p_high, 20.0);
+		assert_eq!(index.remove(c), None);
+		assert_eq!(index.trader_keys("b"), vec![b]);
+		assert_eq!(index.max_price(), Some(15.0));
+
+		assert!(index.audit().is_empty());
+
+		let removed = index.remove_where(|o| o.trader_id == "a");
+		assert_eq!(removed.len(), 1);
+		assert_eq!(index.key_of(1), None);
+		assert_eq!(index.len(), 1);
+	}
+}
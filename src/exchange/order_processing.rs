@@ -1,8 +1,13 @@
-use tokio::net::tcp::TcpStream;
-use crate::order::{Order, OrderType, TradeType, p_wise_dem, p_wise_sup};
-use crate::exchange::queue::Queue;
+use crate::order::{Order, OrderType, TradeType, TimeInForce};
+use crate::order::schedule::ScheduleSpec;
+use crate::exchange::queue::{Queue, Receipt};
+use crate::exchange::config::ExchangeConfig;
+use crate::exchange::self_trade::SelfTradeGuard;
+use crate::exchange::validation::{RejectReason, check_params, check_time_in_force, check_deadline};
+use crate::utility::get_time;
 
 use std::sync::Arc;
+use std::time::Duration;
 use std::thread;
 use std::thread::JoinHandle;
 
@@ -11,6 +16,7 @@ extern crate serde_json;
 extern crate tokio_serde_json;
 
 use tokio::codec::{FramedRead, FramedWrite, LengthDelimitedCodec};
+use tokio::io::{AsyncRead, AsyncWrite};
 use serde_json::Value;
 use tokio_serde_json::{ReadJson, WriteJson};
 
@@ -22,148 +28,169 @@ impl OrderProcessor {
 	// Preprocess message in a new thread and append to queue
 	// order is the trader's order that this function takes ownership of
 	// queue is an Arc clone of the Queue stored on the heap
-	pub fn conc_recv_order(order: Order, queue: Arc<Queue>) -> JoinHandle<()> {
+	// The thread returns what the Queue assigned the order once it is queued
+	pub fn conc_recv_order(order: Order, queue: Arc<Queue>) -> JoinHandle<Receipt> {
 	    thread::spawn(move || {
 	    	// The add function acquires the lock
-	    	queue.add(order);
+	    	queue.add(order)
 	    })
 	}
 }
 
-// Type alias for returning JSON stream
-type DeserializedStream = ReadJson<FramedRead<TcpStream, LengthDelimitedCodec>, serde_json::Value>;
-type SerializedStream = WriteJson<FramedWrite<TcpStream, LengthDelimitedCodec>, serde_json::Value>;
+// Type alias for returning JSON stream over a socket or either half of one
+type DeserializedStream<R> = ReadJson<FramedRead<R, LengthDelimitedCodec>, serde_json::Value>;
+type SerializedStream<W> = WriteJson<FramedWrite<W, LengthDelimitedCodec>, serde_json::Value>;
+
+/// Acknowledgement returned to a trader once their order has been queued.
+/// order_id: Option<u64> -> exchange assigned id to target with updates and cancels
+/// client_order_id: Option<String> -> the trader's own id for the order, if they sent one
+/// trader_id: String -> identifier of the trader
+/// seq: Option<u64> -> sequence number the order was stamped with on arrival
+/// received: Option<Duration> -> time the exchange received the order
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OrderAck {
+	pub order_id: Option<u64>,
+	pub client_order_id: Option<String>,
+	pub trader_id: String,
+	pub seq: Option<u64>,
+	pub received: Option<Duration>,
+}
 
 // A struct for providing stong types to deserialize the incoming JSONs
 #[derive(Deserialize, Debug)]
 pub struct JsonOrder{
 	trader_id: String,        
+	order_id: Option<u64>,
+	client_order_id: Option<String>,
     order_type: String,    
     trade_type: String,  
     p_low: f64,              
     p_high: f64, 
     u_max: f64,       
+    quantity: Option<f64>,
+    schedule: Option<ScheduleSpec>,
+    time_in_force: Option<TimeInForce>,
+    deadline: Option<u64>,
 }
 
 impl JsonOrder {
-	pub fn serializer(socket: TcpStream) -> SerializedStream{
+	pub fn serializer<W: AsyncWrite>(socket: W) -> SerializedStream<W> {
 		// Delimit frames using a length header
 	    let length_delimited = FramedWrite::new(socket, LengthDelimitedCodec::new());
 
 	    // Serialize frames
-	    let serializer = WriteJson::new(length_delimited);
-
-	    serializer
+	    WriteJson::new(length_delimited)
 	}
 
-	pub fn deserialize(socket: TcpStream) ->  DeserializedStream {
+	pub fn deserialize<R: AsyncRead>(socket: R) -> DeserializedStream<R> {
 		// Delimit frames using a length header
 	    let length_delimited = FramedRead::new(socket, LengthDelimitedCodec::new());
 
 	    // Deserialize frames
-	    let deserialized = ReadJson::<_, Value>::new(length_delimited);
-
-	    deserialized
+	    ReadJson::<_, Value>::new(length_delimited)
 	}
-	// Deserialize the JSON, create an Order type, and push onto the queue
-	pub fn process_new(msg: serde_json::Value, queue: Arc<Queue>) {
+	// Deserialize the JSON, create an Order type, and push onto the queue.
+	// Prices and quantities are rounded to the exchange's tick and lot sizes.
+	// Invalid orders never reach the queue and the reason is returned instead.
+	// With a 'guard' orders that would trade with the trader's own orders are
+	// handled on arrival according to the exchange's self-trade prevention.
+	pub fn process_new(msg: serde_json::Value, queue: Arc<Queue>, config: &ExchangeConfig, 
+		guard: Option<&SelfTradeGuard>) -> Result<OrderAck, RejectReason> 
+	{
 		// create Order from JSON
-		let order = JsonOrder::order_from_json(msg);
-
-		if let Some(o) = order {
-			// add message to queue with conc_recv_order()
-			let handle = OrderProcessor::conc_recv_order(o, Arc::clone(&queue));
-			handle.join().unwrap();
-		} else {
-			println!("Unsuccessful json parsing");
-		}
+		let order = JsonOrder::order_from_json(msg, config)?;
+		let client_order_id = order.client_order_id.clone();
+		let trader_id = order.trader_id.clone();
+
+		// add message to queue with conc_recv_order(), or through the guard,
+		// and read back the sequence number it was stamped with
+		let receipt = match guard {
+			Some(guard) => guard.admit(order, &queue, config.self_trade)?,
+			None => OrderProcessor::conc_recv_order(order, Arc::clone(&queue)).join().unwrap(),
+		};
+		Ok(OrderAck { 
+			order_id: receipt.order_id, 
+			client_order_id, 
+			trader_id, 
+			seq: Some(receipt.seq), 
+			received: Some(receipt.received),
+		})
 	}
 
 	// Make an Order from a JSON
-	fn order_from_json(msg: serde_json::Value) -> Option<Order> {
-		let typed_json: JsonOrder = serde_json::from_value(msg).expect("Couldn't make JSON");
+	fn order_from_json(msg: serde_json::Value, config: &ExchangeConfig) -> Result<Order, RejectReason> {
+		let mut typed_json: JsonOrder = serde_json::from_value(msg).map_err(|_| RejectReason::MalformedJson)?;
 		// Parse JSON body into enums compatible with flow market
 		let ot = match typed_json.order_type.to_lowercase().as_ref() {
 			"enter" => OrderType::Enter,
 			"update" => OrderType::Update,
 			"cancel" => OrderType::Cancel,
-			_ => {
-				println!("Entered an invalid ordertype!");
-				return None;
-				},
+			_ => return Err(RejectReason::InvalidOrderType),
 		};
 
 		let tt = match typed_json.trade_type.to_lowercase().as_ref() {
 			"bid" => TradeType::Bid,
 			"ask" => TradeType::Ask,
-			_ => {
-				println!("Entered an invalid tradetype");
-				return None;
-			},
+			_ => return Err(RejectReason::InvalidTradeType),
 		};
 
-		let func = match tt {
-			TradeType::Bid => p_wise_dem(typed_json.p_low, typed_json.p_high, typed_json.u_max),
-			TradeType::Ask => p_wise_sup(typed_json.p_low, typed_json.p_high, typed_json.u_max),
-		};
+		check_params(&typed_json.trader_id, typed_json.p_low, typed_json.p_high, typed_json.u_max, typed_json.quantity)?;
+		let time_in_force = typed_json.time_in_force.unwrap_or_default();
+		check_time_in_force(&time_in_force, get_time())?;
+		if let Some(deadline) = typed_json.deadline {
+			check_deadline(deadline, typed_json.quantity, get_time())?;
+		}
+
+		// Round the prices onto the exchange's tick grid
+		typed_json.p_low = config.snap_price(typed_json.p_low);
+		typed_json.p_high = config.snap_price(typed_json.p_high);
+
+		// Round the total quantity down to whole lots
+		if let Some(q) = typed_json.quantity {
+			let lots = config.snap_quantity(q);
+			if lots <= 0.0 {
+				return Err(RejectReason::QuantityBelowLot);
+			}
+			typed_json.quantity = Some(lots);
+		}
 
-		Some(Order::new(
+		// Rebuild the trader's schedule, piecewise linear if none was sent
+		let spec = typed_json.schedule.unwrap_or(ScheduleSpec::PiecewiseLinear);
+		spec.check(typed_json.p_low).map_err(RejectReason::InvalidSchedule)?;
+
+		// Bids must demand less and asks supply more as the price rises
+		if ot != OrderType::Cancel {
+			spec.validate(&tt, typed_json.p_low, typed_json.p_high, typed_json.u_max)
+				.map_err(RejectReason::InvalidShape)?;
+		}
+
+		let mut order = Order::new(
 			typed_json.trader_id,
 			ot, 
 			tt, 
 			typed_json.p_low, 
 			typed_json.p_high, 
 			typed_json.u_max,
-			func,
-			))
-	}
+			spec,
+			);
 
-	// Turn an order into JSON from its params
-	pub fn order_to_json(order: &Order) -> serde_json::Value {
-		let ot = match order.order_type {
-            OrderType::Enter => "enter",
-            OrderType::Update => "update",
-            OrderType::Cancel => "cancel",
-        };
-
-        let tt = match order.trade_type {
-            TradeType::Bid => "bid",
-            TradeType::Ask => "ask",
-        };
-
-		json!({
-                "trader_id": order.trader_id.clone(),
-                "order_type": ot,
-                "trade_type": tt,
-                "p_low": order.p_low.clone(),
-                "p_high": order.p_high.clone(),
-                "u_max": order.u_max.clone(),
-            })
-	}
+		// The exchange assigns ids to new orders, updates and cancels target an existing one
+		if order.order_type != OrderType::Enter {
+			order.order_id = typed_json.order_id;
+		}
+		order.client_order_id = typed_json.client_order_id;
+		order.time_in_force = time_in_force;
+
+		if let Some(q) = typed_json.quantity {
+			order = order.with_quantity(q);
+		}
 
-	pub fn params_to_json(order_params: (String, OrderType, TradeType, f64, f64, f64)) 
-	-> serde_json::Value {
-		let (t_id, ot, tt, pl, ph, u) = order_params;
-
-		let ot = match ot {
-            OrderType::Enter => "enter",
-            OrderType::Update => "update",
-            OrderType::Cancel => "cancel",
-        };
-
-        let tt = match tt {
-            TradeType::Bid => "bid",
-            TradeType::Ask => "ask",
-        };
-
-		json!({
-                "trader_id": t_id,
-                "order_type": ot,
-                "trade_type": tt,
-                "p_low": pl,
-                "p_high": ph,
-                "u_max": u,
-            })
+		// Deadline orders start trading at the rate that spreads their quantity evenly
+		if let Some(deadline) = typed_json.deadline {
+			order = order.with_deadline(deadline);
+			order.pace(get_time(), 0);
+		}
+		Ok(order)
 	}
 }
 
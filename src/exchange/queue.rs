@@ -1,23 +1,67 @@
-use crate::order::Order;
+use crate::order::{Order, OrderType};
+use crate::utility::get_time;
 use std::sync::Mutex;
+use std::time::Duration;
 
 
+/// What the Queue assigned an order as it was added.
+/// order_id: Option<u64> -> the order's exchange wide order_id, None for updates and cancels without one
+/// seq: u64 -> the order's sequence number, its place in the Queue
+/// received: Duration -> time the order was added to the Queue
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Receipt {
+	pub order_id: Option<u64>,
+	pub seq: u64,
+	pub received: Duration,
+}
+
 /// A threadsafe FIFO queue to store unprocessed messages arriving from traders.
+/// The Queue also assigns every entered order its exchange wide order_id, and
+/// stamps every order with a sequence number on arrival.
 pub struct Queue {
     items: Mutex<Vec<Order>>,
+    next_id: Mutex<u64>,
+    next_seq: Mutex<u64>,
+}
+
+impl Default for Queue {
+	fn default() -> Self {
+		Self::new()
+	}
 }
 
 impl Queue {
 	pub fn new() -> Queue {
 		Queue {
 			items: Mutex::new(Vec::<Order>::new()),
+			next_id: Mutex::new(1),
+			next_seq: Mutex::new(1),
 		}
 	}
 
-	// New orders are pushed to the end of the Queue
-	pub fn add(&self, order: Order) {
+	// New orders are pushed to the end of the Queue. Entered orders without an 
+	// order_id are assigned the next one. Every order is stamped with the next 
+	// sequence number and the time it was received while the Queue is locked, 
+	// so sequence numbers follow the order of the Queue.
+	pub fn add(&self, mut order: Order) -> Receipt {
+		if order.order_type == OrderType::Enter && order.order_id.is_none() {
+			let mut next_id = self.next_id.lock().unwrap();
+			order.order_id = Some(*next_id);
+			*next_id += 1;
+		}
         let mut items = self.items.lock().unwrap();
+		let mut next_seq = self.next_seq.lock().unwrap();
+		let receipt = Receipt { order_id: order.order_id, seq: *next_seq, received: get_time() };
+		*next_seq += 1;
+		order.seq = Some(receipt.seq);
+		order.received = Some(receipt.received);
         items.push(order);
+        receipt
+	}
+
+	// Number of orders that have ever been added, read from the next sequence number
+	pub fn num_arrivals(&self) -> u64 {
+		*self.next_seq.lock().unwrap() - 1
 	}
 
 	pub fn pop(&self) -> Option<Order> {
@@ -25,6 +69,29 @@ impl Queue {
 		items.pop()
 	}
 
+	// Copies of the waiting orders that match 'f', in order of arrival
+	pub fn filter<F: FnMut(&Order) -> bool>(&self, mut f: F) -> Vec<Order> {
+		let items = self.items.lock().unwrap();
+		items.iter().filter(|o| f(o)).cloned().collect()
+	}
+
+	// Removes and returns the waiting orders that match 'f', leaving the rest in order
+	pub fn remove_where<F: FnMut(&Order) -> bool>(&self, mut f: F) -> Vec<Order> {
+		let mut items = self.items.lock().unwrap();
+		let (removed, rest): (Vec<Order>, Vec<Order>) = items.drain(..).partition(|o| f(o));
+		*items = rest;
+		removed
+	}
+
+	// Number of orders waiting to be processed
+	pub fn len(&self) -> usize {
+		self.items.lock().unwrap().len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.len() == 0
+	}
+
 	// Empties the Queue into a vector of Orders. Drain() pops the items
 	// out in the order of arrival, so once iterated upon, orders will be 
 	// processed first -> last.
@@ -1,11 +1,12 @@
 use crate::order::{Order, OrderType, TradeType};
 use crate::exchange::queue::Queue;
 use crate::exchange::order_book::Book;
-use crate::controller::{Task, State};
+use crate::exchange::self_trade::SelfTradeGuard;
+use crate::controller::Task;
 
 use std::thread;
 use std::thread::JoinHandle;
-use std::sync::{Mutex, Arc};
+use std::sync::Arc;
 
 pub struct QueueProcessor {}
 
@@ -50,9 +51,8 @@ impl QueueProcessor {
 	fn process_enter(order: Order, book: Arc<Book>) -> JoinHandle<()> {
 		// Spawn a new thread to process the order
 	    thread::spawn(move || {
-	    	// add_order acquires the lock on the book before mutating
-	    	book.update_max_price(&order.p_high);
-	    	book.update_min_price(&order.p_low);
+	    	// add_order acquires the lock on the book before mutating, the
+	    	// book's price indexes keep its min/max price up to date
 	    	match book.add_order(order) {
 	    		Ok(()) => {},
 	    		Err(e) => {
@@ -65,10 +65,7 @@ impl QueueProcessor {
 
 	// Updates an order in the Bids or Asks Book in it's own thread
 	fn process_update(order: Order, book: Arc<Book>) -> JoinHandle<()> {
-		// update books min/max price if this overwrites current min/max OR this order contains new min/max
 	    thread::spawn(move || {
-	    	let p_high = order.p_high.clone();
-			let p_low = order.p_low.clone();
 	    	// If the order is not found, bubble error up
 	    	match book.update_order(order) {
 	    		Ok(()) => {},
@@ -77,36 +74,12 @@ impl QueueProcessor {
 	    			// TODO send an error response over TCP
 	    		}
 	    	}
-
-	    	let max_p = book.get_max_price();
-	    	let min_p = book.get_min_price();
-
-			if p_high == max_p {
-	    		// The order previously had the max market price
-				book.find_new_max();
-			} else if p_high > max_p {
-				// The order has a new max market price
-				book.update_max_price(&p_high);
-			}
-			
-			if p_low == min_p && p_low != 0.0 {
-				// The order previously had the min market price
-				book.find_new_min();
-				println!("Cancelling old min price");
-			} else if p_low < min_p {
-				// The order has a new min market price
-				book.update_min_price(&p_low);
-			}
-
 	    })
 	}
 
 	// Cancels the order living in the Bids or Asks Book
 	fn process_cancel(order: Order, book: Arc<Book>) -> JoinHandle<()> {
 	    thread::spawn(move || {
-			let p_high = order.p_high.clone();
-			let p_low = order.p_low.clone();
-
 			// If the cancel fails bubble error up.
 			match book.cancel_order(order) {
 	    		Ok(()) => {},
@@ -115,40 +88,44 @@ impl QueueProcessor {
 	    			// TODO send an error response over TCP
 	    		}
 	    	}
+	    })
+	}
 
-			// update min/max if we just cancelled previous min/max
-			if p_high == book.get_max_price() {
-				book.find_new_max();
-			}
-			if p_low == book.get_min_price() && p_low != 0.0 {
-				book.find_new_min();
-				println!("Cancelling old min price");
+	/// Audits both books and prints the report of any book with discrepancies.
+	/// Returns true if both books are consistent.
+	pub fn audit_books(bids: &Book, asks: &Book) -> bool {
+		let mut consistent = true;
+		for report in [bids.audit(), asks.audit()] {
+			if !report.is_ok() {
+				println!("ERROR: {}", report);
+				consistent = false;
 			}
-	    	
-	    })
+		}
+		consistent
 	}
 
 	pub fn async_queue_task(queue: Arc<Queue>, 
 							bids: Arc<Book>, 
 							asks: Arc<Book>, 
-							state: Arc<Mutex<State>>, 
+							guard: Arc<SelfTradeGuard>,
 							duration: u64) -> Task
 	{
 	    Task::rpt_task(move || {
-	    	match *state.lock().expect("Couldn't lock state in queue task") {
-				State::Process => {
-					let handles = QueueProcessor::conc_process_order_queue(Arc::clone(&queue), 
-								Arc::clone(&bids),
-								Arc::clone(&asks));
+	    	// Auctions read snapshots of the books, so the queue is processed while they run.
+	    	// No orders are admitted until the popped orders are all in the books.
+	    	let _paused = guard.pause();
+			let handles = QueueProcessor::conc_process_order_queue(Arc::clone(&queue), 
+						Arc::clone(&bids),
+						Arc::clone(&asks));
 
-					for h in handles {
-						h.join().expect("Couldn't join queue tasks");
-					}
-					// println!("Processing order queue");
-				},
-				State::Auction => println!("Can't process order queue because auction!"),
-				State::PreAuction => println!("Can't process order queue because pre-auction!"),
+			for h in handles {
+				h.join().expect("Couldn't join queue tasks");
+			}
+			// Debug builds check the books are still consistent after every batch
+			if cfg!(debug_assertions) {
+				QueueProcessor::audit_books(&bids, &asks);
 			}
+			// println!("Processing order queue");
 	    }, duration)
 	}
 }
@@ -0,0 +1,406 @@
+use crate::exchange::queue::Queue;
+use crate::exchange::order_book::Book;
+use crate::utility::get_time;
+
+use std::io;
+use std::sync::{Mutex, Arc};
+use std::time::Duration;
+use rand::{Rng, thread_rng};
+
+
+/// How often the Scheduler checks whether the next auction is due (milliseconds)
+pub const POLL_INTERVAL: u64 = 10;
+
+/// Number of batch boundaries the Scheduler remembers
+const MAX_BOUNDARIES: usize = 1000;
+
+/// Number of recent batch boundaries reported in a ScheduleStatus by default
+pub const STATUS_BOUNDARIES: usize = 10;
+
+/// Market activity since the last batch boundary that a schedule can react to.
+/// queued_orders: usize -> orders waiting in the Queue to be processed into the books
+/// arrived_orders: usize -> orders added to the Queue, whether processed or not
+/// book_changes: usize -> orders entered, updated or cancelled in either Book
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Activity {
+	pub queued_orders: usize,
+	pub arrived_orders: usize,
+	pub book_changes: usize,
+}
+
+/// A policy that decides when the next batch auction runs. 'elapsed' is the
+/// time in milliseconds since the last batch boundary.
+pub trait AuctionSchedule: Send {
+	/// A description of the policy that can be shown to clients
+	fn name(&self) -> String;
+
+	/// True once the next auction should run
+	fn is_due(&self, elapsed: u64, activity: &Activity) -> bool;
+
+	/// The longest time since the last boundary that the next auction can take to 
+	/// be due with the activity so far. It may run earlier, ie if more orders arrive.
+	fn due_by(&self, activity: &Activity) -> u64;
+
+	/// Called at every batch boundary, after the auction has been scheduled
+	fn on_boundary(&mut self, _elapsed: u64, _activity: &Activity) {}
+}
+
+/// Runs an auction every 'interval' milliseconds
+pub struct FixedInterval {
+	pub interval: u64,
+}
+
+impl FixedInterval {
+	pub fn new(interval: u64) -> FixedInterval {
+		FixedInterval { interval }
+	}
+}
+
+impl AuctionSchedule for FixedInterval {
+	fn name(&self) -> String {
+		format!("fixed interval {}ms", self.interval)
+	}
+
+	fn is_due(&self, elapsed: u64, _activity: &Activity) -> bool {
+		elapsed >= self.interval
+	}
+
+	fn due_by(&self, _activity: &Activity) -> u64 {
+		self.interval
+	}
+}
+
+/// Runs an auction after an interval drawn uniformly from [min, max] milliseconds,
+/// redrawn after every batch so traders can't time the batch boundary.
+pub struct RandomInterval {
+	pub min: u64,
+	pub max: u64,
+	next: u64,
+}
+
+impl RandomInterval {
+	/// Fails if 'min' is greater than 'max' since there is no interval to draw from
+	pub fn new(min: u64, max: u64) -> io::Result<RandomInterval> {
+		if min > max {
+			return Err(io::Error::new(io::ErrorKind::InvalidInput, 
+				format!("random interval min {}ms is greater than max {}ms", min, max)));
+		}
+		let mut schedule = RandomInterval { min, max, next: min };
+		schedule.draw();
+		Ok(schedule)
+	}
+
+	fn draw(&mut self) {
+		self.next = thread_rng().gen_range(self.min, self.max + 1);
+	}
+}
+
+impl AuctionSchedule for RandomInterval {
+	fn name(&self) -> String {
+		format!("random interval {}-{}ms", self.min, self.max)
+	}
+
+	fn is_due(&self, elapsed: u64, _activity: &Activity) -> bool {
+		elapsed >= self.next
+	}
+
+	// Only the upper bound is shown so the drawn interval stays hidden
+	fn due_by(&self, _activity: &Activity) -> u64 {
+		self.max
+	}
+
+	fn on_boundary(&mut self, _elapsed: u64, _activity: &Activity) {
+		self.draw();
+	}
+}
+
+/// Runs an auction once 'count' orders have arrived since the last auction, or
+/// after 'max_wait' milliseconds so a quiet market still clears. Arrivals are
+/// counted from the Queue's sequence numbers, since the Queue itself is drained
+/// into the books far more often than auctions run.
+pub struct OrderCountTrigger {
+	pub count: usize,
+	pub max_wait: u64,
+}
+
+impl AuctionSchedule for OrderCountTrigger {
+	fn name(&self) -> String {
+		format!("after {} orders or {}ms", self.count, self.max_wait)
+	}
+
+	fn is_due(&self, elapsed: u64, activity: &Activity) -> bool {
+		activity.arrived_orders >= self.count || elapsed >= self.max_wait
+	}
+
+	fn due_by(&self, _activity: &Activity) -> u64 {
+		self.max_wait
+	}
+}
+
+/// Runs an auction once the books have changed 'changes' times since the last
+/// auction, or after 'max_wait' milliseconds so a quiet market still clears.
+pub struct BookChangeTrigger {
+	pub changes: usize,
+	pub max_wait: u64,
+}
+
+impl AuctionSchedule for BookChangeTrigger {
+	fn name(&self) -> String {
+		format!("after {} book changes or {}ms", self.changes, self.max_wait)
+	}
+
+	fn is_due(&self, elapsed: u64, activity: &Activity) -> bool {
+		activity.book_changes >= self.changes || elapsed >= self.max_wait
+	}
+
+	fn due_by(&self, _activity: &Activity) -> u64 {
+		self.max_wait
+	}
+}
+
+/// Runs auctions on an interval that shrinks as the market gets busier. With no
+/// activity the interval is 'max_interval'; when queued orders plus book changes
+/// reach 'load' the interval halves, and it never drops below 'min_interval'.
+pub struct AdaptiveInterval {
+	pub min_interval: u64,
+	pub max_interval: u64,
+	pub load: usize,
+}
+
+impl AdaptiveInterval {
+	/// The interval in milliseconds for the supplied amount of activity
+	pub fn interval(&self, activity: &Activity) -> u64 {
+		let busy = (activity.queued_orders + activity.book_changes) as f64;
+		let load = self.load.max(1) as f64;
+		let interval = self.max_interval as f64 * load / (load + busy);
+		(interval as u64).max(self.min_interval)
+	}
+}
+
+impl AuctionSchedule for AdaptiveInterval {
+	fn name(&self) -> String {
+		format!("adaptive interval {}-{}ms", self.min_interval, self.max_interval)
+	}
+
+	fn is_due(&self, elapsed: u64, activity: &Activity) -> bool {
+		elapsed >= self.interval(activity)
+	}
+
+	fn due_by(&self, activity: &Activity) -> u64 {
+		self.interval(activity)
+	}
+}
+
+/// What clients can see of the auction schedule.
+/// policy: String -> description of the active policy
+/// last_boundary: Duration -> time of the most recent batch boundary
+/// next_boundary: Duration -> latest time the next batch boundary will be at, given
+/// the activity so far. It can come sooner if the policy reacts to more activity.
+/// boundaries: Vec<Duration> -> times of recent batch boundaries, oldest first
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ScheduleStatus {
+	pub policy: String,
+	pub last_boundary: Duration,
+	pub next_boundary: Duration,
+	pub boundaries: Vec<Duration>,
+}
+
+/// Drives the auction task with an AuctionSchedule. The Scheduler measures activity
+/// from the Queue and books, and keeps the times of recent batch boundaries so
+/// clients can see when batches actually ran and which policy is active.
+pub struct Scheduler {
+	policy: Mutex<Box<dyn AuctionSchedule>>,
+	queue: Arc<Queue>,
+	bids: Arc<Book>,
+	asks: Arc<Book>,
+	last_boundary: Mutex<Duration>,
+	last_changes: Mutex<usize>,
+	last_arrivals: Mutex<u64>,
+	boundaries: Mutex<Vec<Duration>>,
+}
+
+impl Scheduler {
+	pub fn new(policy: Box<dyn AuctionSchedule>, queue: Arc<Queue>, bids: Arc<Book>, asks: Arc<Book>) -> Scheduler {
+		let changes = bids.num_changes() + asks.num_changes();
+		let arrivals = queue.num_arrivals();
+		Scheduler {
+			policy: Mutex::new(policy),
+			queue,
+			bids,
+			asks,
+			last_boundary: Mutex::new(get_time()),
+			last_changes: Mutex::new(changes),
+			last_arrivals: Mutex::new(arrivals),
+			boundaries: Mutex::new(Vec::new()),
+		}
+	}
+
+	/// Activity in the market since the last batch boundary
+	pub fn activity(&self) -> Activity {
+		let changes = self.bids.num_changes() + self.asks.num_changes();
+		Activity {
+			queued_orders: self.queue.len(),
+			arrived_orders: self.queue.num_arrivals().saturating_sub(*self.last_arrivals.lock().unwrap()) as usize,
+			book_changes: changes.saturating_sub(*self.last_changes.lock().unwrap()),
+		}
+	}
+
+	/// Checks the policy and if the next auction is due, marks a batch boundary
+	/// and returns the length of the batch that just ended in milliseconds.
+	pub fn poll(&self) -> Option<u64> {
+		let now = get_time();
+		let mut last_boundary = self.last_boundary.lock().unwrap();
+		let elapsed = now.checked_sub(*last_boundary).unwrap_or_default().as_millis() as u64;
+		let activity = self.activity();
+
+		let mut policy = self.policy.lock().unwrap();
+		if !policy.is_due(elapsed, &activity) {
+			return None;
+		}
+		policy.on_boundary(elapsed, &activity);
+
+		*last_boundary = now;
+		*self.last_changes.lock().unwrap() = self.bids.num_changes() + self.asks.num_changes();
+		*self.last_arrivals.lock().unwrap() = self.queue.num_arrivals();
+		let mut boundaries = self.boundaries.lock().unwrap();
+		boundaries.push(now);
+		if boundaries.len() > MAX_BOUNDARIES {
+			boundaries.remove(0);
+		}
+		Some(elapsed)
+	}
+
+	/// Replaces the active policy
+	pub fn set_policy(&self, policy: Box<dyn AuctionSchedule>) {
+		*self.policy.lock().unwrap() = policy;
+	}
+
+	/// The description of the active policy
+	pub fn policy_name(&self) -> String {
+		self.policy.lock().unwrap().name()
+	}
+
+	/// The time of the most recent batch boundary
+	pub fn last_boundary(&self) -> Duration {
+		*self.last_boundary.lock().unwrap()
+	}
+
+	/// The times of recent batch boundaries, oldest first
+	pub fn boundaries(&self) -> Vec<Duration> {
+		self.boundaries.lock().unwrap().clone()
+	}
+
+	/// The active policy and batch boundaries, with the 'last' most recent boundaries
+	pub fn status(&self, last: usize) -> ScheduleStatus {
+		let activity = self.activity();
+		let last_boundary = self.last_boundary();
+		let (policy, due_by) = {
+			let policy = self.policy.lock().unwrap();
+			(policy.name(), policy.due_by(&activity))
+		};
+		let boundaries = self.boundaries.lock().unwrap();
+		ScheduleStatus {
+			policy,
+			last_boundary,
+			next_boundary: last_boundary + Duration::from_millis(due_by),
+			boundaries: boundaries[boundaries.len().saturating_sub(last)..].to_vec(),
+		}
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::order::TradeType;
+
+	#[test]
+	fn test_fixed_and_random_intervals() {
+		let idle = Activity::default();
+		let fixed = FixedInterval::new(3000);
+		assert!(!fixed.is_due(2999, &idle));
+		assert!(fixed.is_due(3000, &idle));
+
+		let mut random = RandomInterval::new(100, 200).unwrap();
+		for _ in 0..20 {
+			assert!(!random.is_due(99, &idle));
+			assert!(random.is_due(200, &idle));
+			random.on_boundary(200, &idle);
+		}
+		assert_eq!(random.due_by(&idle), 200);
+		assert!(RandomInterval::new(100, 100).is_ok());
+		assert!(RandomInterval::new(200, 100).is_err());
+	}
+
+	#[test]
+	fn test_triggered_schedules() {
+		let busy = Activity { queued_orders: 0, arrived_orders: 5, book_changes: 20 };
+		let idle = Activity::default();
+
+		let count = OrderCountTrigger { count: 5, max_wait: 1000 };
+		assert!(count.is_due(0, &busy));
+		assert!(!count.is_due(0, &idle));
+		assert!(count.is_due(1000, &idle));
+
+		let changes = BookChangeTrigger { changes: 30, max_wait: 1000 };
+		assert!(!changes.is_due(0, &busy));
+		assert!(changes.is_due(1000, &busy));
+	}
+
+	#[test]
+	fn test_adaptive_interval() {
+		let adaptive = AdaptiveInterval { min_interval: 100, max_interval: 1000, load: 10 };
+		assert_eq!(adaptive.interval(&Activity::default()), 1000);
+		assert_eq!(adaptive.interval(&Activity { queued_orders: 4, book_changes: 6, ..Activity::default() }), 500);
+		assert_eq!(adaptive.interval(&Activity { queued_orders: 1000, ..Activity::default() }), 100);
+		assert!(adaptive.is_due(500, &Activity { queued_orders: 10, ..Activity::default() }));
+		assert!(!adaptive.is_due(500, &Activity::default()));
+	}
+
+	#[test]
+	fn test_scheduler_boundaries() {
+		let queue = Arc::new(Queue::new());
+		let bids = Arc::new(Book::new(TradeType::Bid));
+		let asks = Arc::new(Book::new(TradeType::Ask));
+		let scheduler = Scheduler::new(Box::new(FixedInterval::new(0)), queue, bids, asks);
+
+		assert_eq!(scheduler.policy_name(), "fixed interval 0ms");
+		assert!(scheduler.poll().is_some());
+		assert!(scheduler.poll().is_some());
+		assert_eq!(scheduler.boundaries().len(), 2);
+		assert_eq!(scheduler.last_boundary(), scheduler.boundaries()[1]);
+
+		scheduler.set_policy(Box::new(FixedInterval::new(60_000)));
+		assert_eq!(scheduler.poll(), None);
+		assert_eq!(scheduler.boundaries().len(), 2);
+
+		let status = scheduler.status(1);
+		assert_eq!(status.policy, "fixed interval 60000ms");
+		assert_eq!(status.boundaries, vec![scheduler.last_boundary()]);
+		assert_eq!(status.next_boundary, status.last_boundary + Duration::from_millis(60_000));
+	}
+
+	#[test]
+	fn test_arrivals_since_boundary() {
+		use crate::order::{Order, OrderType};
+		let queue = Arc::new(Queue::new());
+		let bids = Arc::new(Book::new(TradeType::Bid));
+		let asks = Arc::new(Book::new(TradeType::Ask));
+		let policy = OrderCountTrigger { count: 2, max_wait: 60_000 };
+		let scheduler = Scheduler::new(Box::new(policy), Arc::clone(&queue), bids, asks);
+		let order = || Order::limit(String::from("t"), OrderType::Enter, TradeType::Bid, 50.0, 1.0);
+
+		// Orders count once they arrive, even after the Queue has been drained
+		queue.add(order());
+		queue.pop_all();
+		assert_eq!(scheduler.poll(), None);
+		queue.add(order());
+		queue.pop_all();
+		assert_eq!(scheduler.activity().arrived_orders, 2);
+		assert!(scheduler.poll().is_some());
+
+		// The count starts again from the batch boundary
+		assert_eq!(scheduler.activity().arrived_orders, 0);
+		assert_eq!(scheduler.poll(), None);
+	}
+}
@@ -0,0 +1,368 @@
+use crate::order::{Order, OrderType, TradeType};
+use crate::order::schedule::Schedule;
+use crate::exchange::auction::{AuctionResult, Fill};
+use crate::exchange::order_book::Book;
+use crate::exchange::queue::{Queue, Receipt};
+use crate::exchange::events::{EventQueue, ExchangeEvent};
+use crate::exchange::validation::RejectReason;
+use crate::utility::get_time;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+
+/// How the exchange stops a trader from trading with themselves when they have
+/// orders in both the bids and asks Books.
+/// Off -> self trades are allowed
+/// RejectSecond -> an order is rejected on arrival if the trader already has orders on the other side
+/// CancelOlder -> the trader's orders on the other side are cancelled when a new order arrives
+/// Net -> the trader's own buy and sell rates are netted at the clearing price
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelfTradePrevention {
+	#[default]
+	Off,
+	RejectSecond,
+	CancelOlder,
+	Net,
+}
+
+/// What was done to an order to prevent a self trade
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PreventionAction {
+	Rejected,
+	Cancelled,
+	Netted,
+}
+
+/// An order that self-trade prevention acted on in an auction.
+/// trader_id: String -> identifier of the trader
+/// order_id: Option<u64> -> identifier of the order
+/// trade_type: TradeType{Bid, Ask} -> which book the order was in
+/// action: PreventionAction -> whether the order was rejected, cancelled or netted
+/// rate: f64 -> the rate taken from the order's fill, or for removed orders the rate
+/// it would have traded at the clearing price
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PreventedTrade {
+	pub trader_id: String,
+	pub order_id: Option<u64>,
+	pub trade_type: TradeType,
+	pub action: PreventionAction,
+	pub rate: f64,
+}
+
+/// The self trades prevented in a single auction.
+/// prevented: Vec<PreventedTrade> -> every order that was acted on
+/// volume: f64 -> total rate traders would have traded with themselves at the clearing price
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct SelfTradeReport {
+	pub prevented: Vec<PreventedTrade>,
+	pub volume: f64,
+}
+
+impl SelfTradeReport {
+	pub fn is_empty(&self) -> bool {
+		self.prevented.is_empty()
+	}
+}
+
+/// Checks orders as they arrive for the RejectSecond and CancelOlder modes, so a
+/// trader never holds orders on both sides of the market. The trader's orders on the
+/// other side are those in the opposite Book and the entered orders still waiting in
+/// the Queue, and which came first is decided by sequence number. Orders are admitted
+/// one at a time, and not while the queue processor moves orders into the Books.
+/// bids: Arc<Book> -> the bids Book
+/// asks: Arc<Book> -> the asks Book
+/// events: Arc<EventQueue> -> where traders are told their orders were removed
+/// admitting: Mutex<()> -> held while an order is admitted or the queue is processed
+/// prevented: Mutex<Vec<(Order, PreventionAction)>> -> orders acted on since the last auction
+pub struct SelfTradeGuard {
+	bids: Arc<Book>,
+	asks: Arc<Book>,
+	events: Arc<EventQueue>,
+	admitting: Mutex<()>,
+	prevented: Mutex<Vec<(Order, PreventionAction)>>,
+}
+
+impl SelfTradeGuard {
+	pub fn new(bids: Arc<Book>, asks: Arc<Book>, events: Arc<EventQueue>) -> SelfTradeGuard {
+		SelfTradeGuard {
+			bids,
+			asks,
+			events,
+			admitting: Mutex::new(()),
+			prevented: Mutex::new(Vec::new()),
+		}
+	}
+
+	/// Stops orders being admitted until the returned lock is dropped. The queue processor
+	/// holds it from taking orders off the Queue until they are all in the Books, since
+	/// until then they are in neither and admit couldn't see them.
+	pub fn pause(&self) -> MutexGuard<'_, ()> {
+		self.admitting.lock().unwrap()
+	}
+
+	/// Adds an order to the Queue unless it conflicts with the trader's orders on the other
+	/// side under 'mode', and returns what the Queue assigned it. Orders are admitted one
+	/// at a time and stamped as they are added, so an arriving order always comes after
+	/// the orders it is checked against. Under RejectSecond the order is rejected if there
+	/// are any, under CancelOlder they are cancelled. Only entered orders are checked.
+	pub fn admit(&self, order: Order, queue: &Queue, mode: SelfTradePrevention) -> Result<Receipt, RejectReason> {
+		let _admitting = self.admitting.lock().unwrap();
+		let mut prevented = self.prevented.lock().unwrap();
+		let action = match mode {
+			SelfTradePrevention::RejectSecond => PreventionAction::Rejected,
+			SelfTradePrevention::CancelOlder => PreventionAction::Cancelled,
+			_ => return Ok(queue.add(order)),
+		};
+		if order.order_type != OrderType::Enter {
+			return Ok(queue.add(order));
+		}
+
+		let opposite = |o: &Order| o.trader_id == order.trader_id && o.trade_type != order.trade_type;
+		let book = match order.trade_type {
+			TradeType::Bid => &self.asks,
+			TradeType::Ask => &self.bids,
+		};
+		// Only the trader's own orders are looked up, through the Book's trader index
+		let resting: Vec<Order> = book.trader_orders(&order.trader_id).into_iter()
+			.filter_map(|id| book.peek_order(id))
+			.collect();
+		let queued = queue.filter(|o| o.order_type == OrderType::Enter && opposite(o));
+
+		// The order is the second under RejectSecond whenever the trader has orders on the other side
+		if mode == SelfTradePrevention::RejectSecond && !(resting.is_empty() && queued.is_empty()) {
+			prevented.push((order, action));
+			return Err(RejectReason::SelfTrade);
+		}
+
+		// Otherwise every order on the other side is older and loses, and its trader is told
+		let mut removed = queue.remove_where(|o| o.order_type == OrderType::Enter && opposite(o));
+		for other in resting.into_iter() {
+			if book.cancel_order(other.clone()).is_ok() {
+				removed.push(other);
+			}
+		}
+		let now = get_time();
+		for other in removed.into_iter() {
+			self.events.push(ExchangeEvent::SelfTradePrevented {
+				trader_id: other.trader_id.clone(),
+				order_id: other.order_id,
+				trade_type: other.trade_type.clone(),
+				action,
+				timestamp: now,
+			});
+			prevented.push((other, action));
+		}
+		Ok(queue.add(order))
+	}
+
+	/// Takes the orders acted on since the last call, to report against the next auction
+	pub fn take(&self) -> Vec<(Order, PreventionAction)> {
+		self.prevented.lock().unwrap().drain(..).collect()
+	}
+}
+
+/// Nets each trader's bid fills against their ask fills, taking the smaller of their
+/// total buy and sell rates from both sides pro rata. Called at the clearing price
+/// before the fills are rationed.
+pub fn net_fills(bid_fills: &mut [Fill], ask_fills: &mut [Fill]) -> SelfTradeReport {
+	let totals = |fills: &[Fill]| {
+		let mut totals = BTreeMap::<String, f64>::new();
+		for fill in fills.iter() {
+			*totals.entry(fill.trader_id.clone()).or_insert(0.0) += fill.rate;
+		}
+		totals
+	};
+	let (bought, sold) = (totals(bid_fills), totals(ask_fills));
+
+	let mut report = SelfTradeReport::default();
+	let mut netted = BTreeMap::<String, f64>::new();
+	for (trader_id, b) in bought.iter() {
+		let n = b.min(*sold.get(trader_id).unwrap_or(&0.0));
+		if n > 0.0 {
+			netted.insert(trader_id.clone(), n);
+			report.volume += n;
+		}
+	}
+
+	for (fills, totals) in [(bid_fills, &bought), (ask_fills, &sold)] {
+		for fill in fills.iter_mut() {
+			if let Some(n) = netted.get(&fill.trader_id) {
+				let rate = fill.rate * n / totals[&fill.trader_id];
+				fill.rate -= rate;
+				report.prevented.push(PreventedTrade {
+					trader_id: fill.trader_id.clone(),
+					order_id: fill.order_id,
+					trade_type: fill.trade_type.clone(),
+					action: PreventionAction::Netted,
+					rate,
+				});
+			}
+		}
+	}
+	report
+}
+
+/// Reports the orders a SelfTradeGuard acted on against the auction that followed.
+/// A trader's prevented volume is the smaller of what they would have bought and sold
+/// at the clearing price had their removed orders stayed in the Books.
+pub fn report_removed(removed: &[(Order, PreventionAction)], result: &AuctionResult) -> SelfTradeReport {
+	let rate_of = |order: &Order| result.clearing_price.map_or(0.0, |p| order.rate(p));
+	let mut report = SelfTradeReport::default();
+
+	// Total buy and sell rates of every trader that had orders removed
+	let mut rates = BTreeMap::<&String, (f64, f64)>::new();
+	for (order, action) in removed.iter() {
+		let rate = rate_of(order);
+		let entry = rates.entry(&order.trader_id).or_insert((0.0, 0.0));
+		match order.trade_type {
+			TradeType::Bid => entry.0 += rate,
+			TradeType::Ask => entry.1 += rate,
+		}
+		report.prevented.push(PreventedTrade {
+			trader_id: order.trader_id.clone(),
+			order_id: order.order_id,
+			trade_type: order.trade_type.clone(),
+			action: *action,
+			rate,
+		});
+	}
+	for fill in result.bid_fills.iter().chain(result.ask_fills.iter()) {
+		if let Some(entry) = rates.get_mut(&fill.trader_id) {
+			match fill.trade_type {
+				TradeType::Bid => entry.0 += fill.rate,
+				TradeType::Ask => entry.1 += fill.rate,
+			}
+		}
+	}
+
+	report.volume = rates.values().map(|(b, s)| b.min(*s)).sum();
+	report
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::exchange::auction::ClearingOutcome;
+
+	fn limit(trader_id: &str, trade_type: TradeType, u_max: f64) -> Order {
+		Order::limit(String::from(trader_id), OrderType::Enter, trade_type, 50.0, u_max)
+	}
+
+	// Adds the order to the book under an order_id, as the queue processor would
+	fn rest(book: &Book, mut order: Order) {
+		order.order_id = Some(100 + book.len() as u64);
+		book.add_order(order).unwrap();
+	}
+
+	fn setup() -> (SelfTradeGuard, Arc<Book>, Queue, Arc<EventQueue>) {
+		let (bids, asks) = (Arc::new(Book::new(TradeType::Bid)), Arc::new(Book::new(TradeType::Ask)));
+		let events = Arc::new(EventQueue::new());
+		let guard = SelfTradeGuard::new(Arc::clone(&bids), asks, Arc::clone(&events));
+		(guard, bids, Queue::new(), events)
+	}
+
+	#[test]
+	fn test_reject_second() {
+		let (guard, bids, queue, events) = setup();
+		let mode = SelfTradePrevention::RejectSecond;
+		rest(&bids, limit("both", TradeType::Bid, 10.0));
+
+		// The ask arrived second so it is refused, other traders are unaffected
+		assert_eq!(guard.admit(limit("both", TradeType::Ask, 4.0), &queue, mode), Err(RejectReason::SelfTrade));
+		assert!(guard.admit(limit("seller", TradeType::Ask, 4.0), &queue, mode).is_ok());
+		assert_eq!(queue.len(), 1);
+		assert_eq!(bids.len(), 1);
+
+		// Orders still in the queue came first too
+		queue.add(limit("racer", TradeType::Ask, 4.0));
+		assert_eq!(guard.admit(limit("racer", TradeType::Bid, 4.0), &queue, mode), Err(RejectReason::SelfTrade));
+		assert_eq!(queue.filter(|o| o.trader_id == "racer").len(), 1);
+		assert!(events.is_empty());
+
+		let prevented = guard.take();
+		let actions: Vec<(&str, PreventionAction)> = prevented.iter().map(|(o, a)| (o.trader_id.as_str(), *a)).collect();
+		assert_eq!(actions, vec![("both", PreventionAction::Rejected), ("racer", PreventionAction::Rejected)]);
+		assert!(guard.take().is_empty());
+	}
+
+	#[test]
+	fn test_cancel_older() {
+		let (guard, bids, queue, events) = setup();
+		let mode = SelfTradePrevention::CancelOlder;
+		rest(&bids, limit("both", TradeType::Bid, 10.0));
+		queue.add(limit("both", TradeType::Bid, 6.0));
+		let changes = bids.num_changes();
+
+		// The resting and queued bids are both older than the ask, so both are cancelled
+		let receipt = guard.admit(limit("both", TradeType::Ask, 4.0), &queue, mode).unwrap();
+		assert_eq!(receipt.seq, 2);
+		assert!(bids.is_empty());
+		assert_eq!(bids.num_changes(), changes + 1);
+		assert_eq!(queue.filter(|o| o.trade_type == TradeType::Bid).len(), 0);
+		assert_eq!(events.pop_for("both").len(), 2);
+
+		// The next bid cancels the queued ask in turn
+		assert!(guard.admit(limit("both", TradeType::Bid, 4.0), &queue, mode).is_ok());
+		assert_eq!(queue.filter(|o| o.trade_type == TradeType::Bid).len(), 1);
+		assert_eq!(queue.len(), 1);
+		assert_eq!(guard.take().len(), 3);
+	}
+
+	#[test]
+	fn test_pause_admission() {
+		let (guard, _, queue, _) = setup();
+		let (guard, queue) = (Arc::new(guard), Arc::new(queue));
+		let paused = guard.pause();
+		let arrival = {
+			let (guard, queue) = (Arc::clone(&guard), Arc::clone(&queue));
+			std::thread::spawn(move || {
+				guard.admit(limit("both", TradeType::Ask, 4.0), &queue, SelfTradePrevention::RejectSecond)
+			})
+		};
+
+		// The order waits until the queue processor is done with the books
+		std::thread::sleep(std::time::Duration::from_millis(50));
+		assert!(queue.is_empty());
+		drop(paused);
+		assert!(arrival.join().unwrap().is_ok());
+		assert_eq!(queue.len(), 1);
+	}
+
+	#[test]
+	fn test_report_removed() {
+		let removed = vec![(limit("both", TradeType::Ask, 4.0), PreventionAction::Rejected)];
+		let mut result = AuctionResult::no_cross(ClearingOutcome::EmptyBids { num_asks: 0 });
+		result.clearing_price = Some(50.0);
+		result.bid_fills = vec![Fill::of("both", TradeType::Bid, 10.0), Fill::of("buyer", TradeType::Bid, 5.0)];
+
+		// "both" would have sold 4 of the 10 they bought
+		let report = report_removed(&removed, &result);
+		assert_eq!(report.volume, 4.0);
+		assert_eq!(report.prevented.len(), 1);
+		assert_eq!((report.prevented[0].rate, report.prevented[0].action), (4.0, PreventionAction::Rejected));
+
+		// Nothing is prevented without a cross
+		let report = report_removed(&removed, &AuctionResult::no_cross(ClearingOutcome::EmptyBids { num_asks: 0 }));
+		assert_eq!(report.volume, 0.0);
+	}
+
+	#[test]
+	fn test_net_fills() {
+		let mut bid_fills = vec![Fill::of("both", TradeType::Bid, 6.0), Fill::of("both", TradeType::Bid, 2.0), Fill::of("buyer", TradeType::Bid, 5.0)];
+		let mut ask_fills = vec![Fill::of("both", TradeType::Ask, 4.0), Fill::of("seller", TradeType::Ask, 9.0)];
+
+		let report = net_fills(&mut bid_fills, &mut ask_fills);
+		assert_eq!(report.volume, 4.0);
+		assert_eq!(report.prevented.len(), 3);
+
+		// The trader's 8 bought and 4 sold net to 4 bought, taken pro rata
+		let rates: Vec<f64> = bid_fills.iter().map(|f| f.rate).collect();
+		assert_eq!(rates, vec![3.0, 1.0, 5.0]);
+		assert_eq!(ask_fills[0].rate, 0.0);
+		assert_eq!(ask_fills[1].rate, 9.0);
+	}
+}
@@ -0,0 +1,208 @@
+use crate::exchange::auction::AuctionResult;
+use crate::order::TradeType;
+use crate::utility::get_time;
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+
+/// A trader's holdings as settled by the exchange.
+/// position: f64 -> net units of the asset held, negative when short
+/// cash: f64 -> net cash balance, negative when the trader owes the exchange
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct Account {
+	pub position: f64,
+	pub cash: f64,
+}
+
+/// A single settled fill for one trader in one batch.
+/// batch: u64 -> sequence number of the auction the trade cleared in
+/// trader_id: String -> identifier of the trader
+/// order_id: Option<u64> -> identifier of the order that traded
+/// trade_type: TradeType{Bid, Ask} -> whether the trader bought or sold
+/// price: f64 -> the batch's clearing price
+/// quantity: f64 -> units traded, the fill rate times the batch duration
+/// timestamp: Duration -> time the trade was settled
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Trade {
+	pub batch: u64,
+	pub trader_id: String,
+	pub order_id: Option<u64>,
+	pub trade_type: TradeType,
+	pub price: f64,
+	pub quantity: f64,
+	pub timestamp: Duration,
+}
+
+/// Every settled trade in order of settlement, indexed by trader_id so a
+/// trader's history doesn't scan everyone else's trades.
+#[derive(Default)]
+struct TradeLog {
+	trades: Vec<Trade>,
+	by_trader: HashMap<String, Vec<usize>>,
+}
+
+/// Keeps a per trader_id ledger of asset position and cash. Flow orders trade
+/// a rate, so each auction's fills are settled as rate * batch duration at the
+/// clearing price.
+pub struct Ledger {
+	accounts: Mutex<HashMap<String, Account>>,
+	trades: Mutex<TradeLog>,
+	batch: Mutex<u64>,
+}
+
+impl Default for Ledger {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Ledger {
+	pub fn new() -> Ledger {
+		Ledger {
+			accounts: Mutex::new(HashMap::new()),
+			trades: Mutex::new(TradeLog::default()),
+			batch: Mutex::new(0),
+		}
+	}
+
+	/// Posts every fill of the auction result to the ledger. 'duration' is the
+	/// length of the batch in milliseconds. Returns the trades that were settled.
+	pub fn settle(&self, result: &AuctionResult, duration: u64) -> Vec<Trade> {
+		let batch = {
+			let mut batch = self.batch.lock().unwrap();
+			*batch += 1;
+			*batch
+		};
+
+		let price = match result.clearing_price {
+			Some(p) => p,
+			None => return Vec::new(),
+		};
+
+		let secs = duration as f64 / 1000.0;
+		let timestamp = get_time();
+
+		let new_trades: Vec<Trade> = result.bid_fills.iter()
+			.chain(result.ask_fills.iter())
+			.filter(|fill| fill.rate > 0.0)
+			.map(|fill| Trade {
+				batch,
+				trader_id: fill.trader_id.clone(),
+				order_id: fill.order_id,
+				trade_type: fill.trade_type.clone(),
+				price,
+				quantity: fill.rate * secs,
+				timestamp,
+			})
+			.collect();
+
+		{
+			let mut accounts = self.accounts.lock().unwrap();
+			for trade in &new_trades {
+				let account = accounts.entry(trade.trader_id.clone()).or_default();
+				match trade.trade_type {
+					TradeType::Bid => {
+						account.position += trade.quantity;
+						account.cash -= trade.quantity * trade.price;
+					},
+					TradeType::Ask => {
+						account.position -= trade.quantity;
+						account.cash += trade.quantity * trade.price;
+					},
+				}
+			}
+		}
+
+		{
+			let mut log = self.trades.lock().unwrap();
+			for trade in &new_trades {
+				let index = log.trades.len();
+				log.by_trader.entry(trade.trader_id.clone()).or_default().push(index);
+				log.trades.push(trade.clone());
+			}
+		}
+		new_trades
+	}
+
+	/// Returns the trader's current balances if they have ever traded
+	pub fn get_account(&self, trader_id: &str) -> Option<Account> {
+		self.accounts.lock().unwrap().get(trader_id).cloned()
+	}
+
+	/// Returns every trade the trader has settled in order of settlement
+	pub fn trade_history(&self, trader_id: &str) -> Vec<Trade> {
+		let log = self.trades.lock().unwrap();
+		match log.by_trader.get(trader_id) {
+			Some(indices) => indices.iter().map(|&i| log.trades[i].clone()).collect(),
+			None => Vec::new(),
+		}
+	}
+
+	/// Returns every trade settled in the given batch
+	pub fn batch_trades(&self, batch: u64) -> Vec<Trade> {
+		self.trades.lock().unwrap().trades.iter()
+			.filter(|t| t.batch == batch)
+			.cloned()
+			.collect()
+	}
+
+	/// Number of auctions that have been posted to the ledger
+	pub fn num_batches(&self) -> u64 {
+		*self.batch.lock().unwrap()
+	}
+
+	/// Utility for seeing how many traders hold an account
+	pub fn num_accounts(&self) -> usize {
+		self.accounts.lock().unwrap().len()
+	}
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::exchange::auction::{Fill, ClearingOutcome};
+
+	#[test]
+	fn test_settle_fills() {
+		let ledger = Ledger::new();
+		let mut result = AuctionResult::no_cross(ClearingOutcome::EmptyBids { num_asks: 0 });
+		result.clearing_price = Some(10.0);
+		result.bid_fills = vec![Fill::of("buyer", TradeType::Bid, 4.0), Fill::of("idle", TradeType::Bid, 0.0)];
+		result.ask_fills = vec![Fill::of("seller", TradeType::Ask, 4.0)];
+
+		// A 500ms batch trades half of each rate
+		let trades = ledger.settle(&result, 500);
+		assert_eq!(trades.len(), 2);
+
+		let buyer = ledger.get_account("buyer").unwrap();
+		assert_eq!(buyer.position, 2.0);
+		assert_eq!(buyer.cash, -20.0);
+
+		let seller = ledger.get_account("seller").unwrap();
+		assert_eq!(seller.position, -2.0);
+		assert_eq!(seller.cash, 20.0);
+
+		// Zero rate fills don't open an account
+		assert_eq!(ledger.get_account("idle"), None);
+
+		ledger.settle(&result, 1000);
+		assert_eq!(ledger.get_account("buyer").unwrap().position, 6.0);
+		assert_eq!(ledger.trade_history("buyer").len(), 2);
+		assert_eq!(ledger.trade_history("seller")[1].batch, 2);
+		assert!(ledger.trade_history("idle").is_empty());
+		assert_eq!(ledger.batch_trades(2)[0].quantity, 4.0);
+		assert_eq!(ledger.num_batches(), 2);
+	}
+
+	#[test]
+	fn test_settle_no_cross() {
+		let ledger = Ledger::new();
+		let trades = ledger.settle(&AuctionResult::no_cross(ClearingOutcome::EmptyBids { num_asks: 0 }), 1000);
+		assert!(trades.is_empty());
+		assert_eq!(ledger.num_accounts(), 0);
+		assert_eq!(ledger.num_batches(), 1);
+	}
+}
@@ -0,0 +1,167 @@
+use crate::order::TimeInForce;
+
+use std::fmt;
+use std::time::Duration;
+
+
+/// The reasons the exchange refuses an order before it reaches the Queue. Each
+/// reason has a stable numeric code that can be reported back to clients.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum RejectReason {
+	/// The message couldn't be deserialized into an order
+	MalformedJson,
+	/// order_type wasn't "enter", "update" or "cancel"
+	InvalidOrderType,
+	/// trade_type wasn't "bid" or "ask"
+	InvalidTradeType,
+	/// trader_id was empty or only whitespace
+	EmptyTraderId,
+	/// The named field was NaN or infinite
+	NonFinite(&'static str),
+	/// p_low was greater than p_high
+	InvertedPrices,
+	/// u_max was negative
+	NegativeRate,
+	/// quantity was smaller than one lot
+	QuantityBelowLot,
+	/// The schedule can't be evaluated
+	InvalidSchedule(&'static str),
+	/// The schedule has the wrong shape for its trade type
+	InvalidShape(&'static str),
+	/// The time in force would expire the order before it could trade
+	InvalidTimeInForce,
+	/// The deadline has passed or the order has no quantity to trade by it
+	InvalidDeadline,
+	/// quantity was zero or negative
+	InvalidQuantity,
+	/// The order would trade against the trader's own orders on the other side
+	SelfTrade,
+}
+
+impl RejectReason {
+	/// The numeric code reported to clients
+	pub fn code(&self) -> u32 {
+		match self {
+			RejectReason::MalformedJson => 1,
+			RejectReason::InvalidOrderType => 2,
+			RejectReason::InvalidTradeType => 3,
+			RejectReason::EmptyTraderId => 4,
+			RejectReason::NonFinite(_) => 5,
+			RejectReason::InvertedPrices => 6,
+			RejectReason::NegativeRate => 7,
+			RejectReason::QuantityBelowLot => 8,
+			RejectReason::InvalidSchedule(_) => 9,
+			RejectReason::InvalidShape(_) => 10,
+			RejectReason::InvalidTimeInForce => 11,
+			RejectReason::InvalidDeadline => 12,
+			RejectReason::InvalidQuantity => 13,
+			RejectReason::SelfTrade => 14,
+		}
+	}
+}
+
+impl fmt::Display for RejectReason {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			RejectReason::MalformedJson => write!(f, "malformed order JSON"),
+			RejectReason::InvalidOrderType => write!(f, "invalid order_type"),
+			RejectReason::InvalidTradeType => write!(f, "invalid trade_type"),
+			RejectReason::EmptyTraderId => write!(f, "empty trader_id"),
+			RejectReason::NonFinite(field) => write!(f, "{} is not finite", field),
+			RejectReason::InvertedPrices => write!(f, "p_low is greater than p_high"),
+			RejectReason::NegativeRate => write!(f, "u_max is negative"),
+			RejectReason::QuantityBelowLot => write!(f, "quantity is smaller than the lot size"),
+			RejectReason::InvalidSchedule(e) => write!(f, "schedule can't be evaluated: {}", e),
+			RejectReason::InvalidShape(e) => write!(f, "schedule has an invalid shape: {}", e),
+			RejectReason::InvalidTimeInForce => write!(f, "time in force has already expired"),
+			RejectReason::InvalidDeadline => write!(f, "deadline has passed or the order has no quantity"),
+			RejectReason::InvalidQuantity => write!(f, "quantity is zero or negative"),
+			RejectReason::SelfTrade => write!(f, "order would trade with the trader's own orders"),
+		}
+	}
+}
+
+/// Checks an order's parameters are usable by the exchange. Non-finite values are
+/// rejected first since they make every other comparison meaningless.
+pub fn check_params(trader_id: &str, p_low: f64, p_high: f64, u_max: f64, quantity: Option<f64>)
+-> Result<(), RejectReason>
+{
+	if trader_id.trim().is_empty() {
+		return Err(RejectReason::EmptyTraderId);
+	}
+
+	let fields = [("p_low", p_low), ("p_high", p_high), ("u_max", u_max)];
+	if let Some((field, _)) = fields.iter().find(|(_, v)| !v.is_finite()) {
+		return Err(RejectReason::NonFinite(field));
+	}
+	if let Some(q) = quantity {
+		if !q.is_finite() {
+			return Err(RejectReason::NonFinite("quantity"));
+		}
+	}
+
+	if p_low > p_high {
+		return Err(RejectReason::InvertedPrices);
+	}
+	if u_max < 0.0 {
+		return Err(RejectReason::NegativeRate);
+	}
+	if quantity.is_some_and(|q| q <= 0.0) {
+		return Err(RejectReason::InvalidQuantity);
+	}
+	Ok(())
+}
+
+/// Checks a time in force leaves the order at least one auction to trade in, 
+/// where 'now' is the time the order arrived.
+pub fn check_time_in_force(time_in_force: &TimeInForce, now: Duration) -> Result<(), RejectReason> {
+	match time_in_force {
+		TimeInForce::GoodForAuctions { auctions: 0 } => Err(RejectReason::InvalidTimeInForce),
+		TimeInForce::GoodTillTime { expires } if *expires <= now.as_millis() as u64 => {
+			Err(RejectReason::InvalidTimeInForce)
+		},
+		_ => Ok(()),
+	}
+}
+
+/// Checks a deadline order has a quantity to spread and a deadline after 'now', the
+/// time the order arrived.
+pub fn check_deadline(deadline: u64, quantity: Option<f64>, now: Duration) -> Result<(), RejectReason> {
+	if quantity.is_none() || deadline <= now.as_millis() as u64 {
+		return Err(RejectReason::InvalidDeadline);
+	}
+	Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_check_params() {
+		assert_eq!(check_params("trader", 1.0, 2.0, 3.0, None), Ok(()));
+		assert_eq!(check_params("trader", 2.0, 2.0, 0.0, Some(1.0)), Ok(()));
+
+		assert_eq!(check_params("  ", 1.0, 2.0, 3.0, None), Err(RejectReason::EmptyTraderId));
+		assert_eq!(check_params("trader", f64::NAN, 2.0, 3.0, None), Err(RejectReason::NonFinite("p_low")));
+		assert_eq!(check_params("trader", 1.0, f64::INFINITY, 3.0, None), Err(RejectReason::NonFinite("p_high")));
+		assert_eq!(check_params("trader", 1.0, 2.0, 3.0, Some(f64::NAN)), Err(RejectReason::NonFinite("quantity")));
+		assert_eq!(check_params("trader", 3.0, 2.0, 3.0, None), Err(RejectReason::InvertedPrices));
+		assert_eq!(check_params("trader", 1.0, 2.0, -3.0, None), Err(RejectReason::NegativeRate));
+		assert_eq!(check_params("trader", 1.0, 2.0, 3.0, Some(0.0)), Err(RejectReason::InvalidQuantity));
+		assert_eq!(check_params("trader", 1.0, 2.0, 3.0, Some(-1.0)), Err(RejectReason::InvalidQuantity));
+
+		let now = Duration::from_millis(10_000);
+		assert_eq!(check_time_in_force(&TimeInForce::ImmediateOrCancel, now), Ok(()));
+		assert_eq!(check_time_in_force(&TimeInForce::GoodForAuctions { auctions: 0 }, now), Err(RejectReason::InvalidTimeInForce));
+		assert_eq!(check_time_in_force(&TimeInForce::GoodTillTime { expires: 10_000 }, now), Err(RejectReason::InvalidTimeInForce));
+		assert_eq!(check_time_in_force(&TimeInForce::GoodTillTime { expires: 10_001 }, now), Ok(()));
+		assert_eq!(check_deadline(20_000, Some(5.0), now), Ok(()));
+		assert_eq!(check_deadline(20_000, None, now), Err(RejectReason::InvalidDeadline));
+		assert_eq!(check_deadline(10_000, Some(5.0), now), Err(RejectReason::InvalidDeadline));
+
+		assert_eq!(RejectReason::InvertedPrices.code(), 6);
+		assert_eq!(RejectReason::NonFinite("u_max").to_string(), "u_max is not finite");
+	}
+}
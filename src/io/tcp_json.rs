@@ -1,5 +1,5 @@
 use crate::exchange::order_processing::JsonOrder;
-use crate::exchange::queue::Queue;
+use crate::exchange::gateway::Gateway;
 use crate::controller::Task;
 
 use tokio::net::{TcpListener, TcpStream};
@@ -7,9 +7,11 @@ use tokio::prelude::*;
 use std::sync::Arc;
 
 /// A simple tcp server that listens for incoming messages asynchronously. Each message
-/// is parsed from a JSON into the internal Order type used in the exchange. This function
-/// returns an AsnycTask to be used by the Controller module running Tokio.
-pub fn tcp_listener(queue: Arc<Queue>, address: String) -> Task { 
+/// is handled by the Gateway: orders are parsed from JSON into the internal Order type 
+/// used in the exchange and queries are answered. The reply is written back over the 
+/// same connection. This function returns an AsnycTask to be used by the Controller 
+/// module running Tokio.
+pub fn tcp_listener(gateway: Arc<Gateway>, address: String) -> Task { 
 	 // Bind a TcpListener to a local port
 	let addr = address.parse().unwrap();
 	let listener = TcpListener::bind(&addr).unwrap();
@@ -18,17 +20,20 @@ pub fn tcp_listener(queue: Arc<Queue>, address: String) -> Task {
 
 	// start a tcp server that accepts JSON objects 
 	let tcp_server = listener.incoming().for_each(move |socket| {
-		// Clone the queue into the closure
-		let queue = Arc::clone(&queue);
-
-		// Deserialize the stream from the socket
-        let deserialized = JsonOrder::deserialize(socket).map_err(|e| println!("ERR: {:?}", e));
-
-        // Spawn a task that converts JSON to an Order and adds to queue
-        tokio::spawn(deserialized.for_each(move |msg| {
-            JsonOrder::process_new(msg, Arc::clone(&queue));
-            Ok(())
-        }));
+		// Clone the gateway into the closure
+		let gateway = Arc::clone(&gateway);
+
+		// Read messages from one half of the socket and reply on the other
+		let (reader, writer) = socket.split();
+        let deserialized = JsonOrder::deserialize(reader);
+        let serialized = JsonOrder::serializer(writer);
+
+        // Spawn a task that handles each message and sends back its reply
+        tokio::spawn(deserialized
+        	.map(move |msg| gateway.handle(msg))
+        	.forward(serialized)
+        	.map(|_| ())
+        	.map_err(|e| println!("ERR: {:?}", e)));
 
         Ok(())
     })
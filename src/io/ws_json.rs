@@ -1,5 +1,5 @@
-use crate::exchange::order_processing::JsonOrder;
-use crate::exchange::queue::Queue;
+use crate::exchange::gateway::Gateway;
+use crate::exchange::validation::RejectReason;
 
 use std::thread;
 use std::sync::Arc;
@@ -10,41 +10,35 @@ use serde_json;
 // WebSocket handler
 struct Server {
     out: Sender,
-    queue: Arc<Queue>,
+    gateway: Arc<Gateway>,
 }
 
 /// A simple websocket server that listens for incoming messages asynchronously. Each message
-/// is parsed from a JSON into the internal Order type used in the exchange. 
+/// is handled by the Gateway: orders are parsed from a JSON into the internal Order type 
+/// used in the exchange and queries are answered.
 impl Handler for Server {
     fn on_message(&mut self, msg: Message) -> Result<()> {
         // println!("Server got message '{}'. ", msg);
 
-        // Clone the queue into the closure
-		let queue = Arc::clone(&self.queue);
-
 		// Consume websocket message converting to string
-		if let Ok(text) = msg.into_text() {
-			match serde_json::from_str::<serde_json::Value>(&text) {
-				Ok(json) => {
-		            JsonOrder::process_new(json, Arc::clone(&queue));
-				},
-				Err(e) => {
-					println!("Could not parse JSON: {:?}", e);
-				},
-			}
-		} 
-
-        self.out.send("Parsed message!")
+		// Acknowledge the order with its order_id, answer the query, or tell the trader 
+		// why it was rejected
+		let reply = match msg.into_text().ok().and_then(|text| serde_json::from_str::<serde_json::Value>(&text).ok()) {
+			Some(json) => self.gateway.handle(json),
+			None => Gateway::reject(RejectReason::MalformedJson),
+		};
+
+        self.out.send(reply.to_string())
     }
 }
 
-pub fn ws_listener(queue: Arc<Queue>, addr: &'static str) -> thread::JoinHandle<()> { 
+pub fn ws_listener(gateway: Arc<Gateway>, addr: &'static str) -> thread::JoinHandle<()> { 
 	env_logger::init();
     thread::spawn(move || {
     	listen(addr, |out| {
 	         Server {
-	         	out: out,
-	         	queue: Arc::clone(&queue),
+	         	out,
+	         	gateway: Arc::clone(&gateway),
 	         }
 	    }).expect("Error with WS Server...");
     })
@@ -70,9 +64,9 @@ impl Handler for Client {
 /// A simple websocket server that sends jsons. Each message
 /// is parsed from a JSON into the internal Order type used in the exchange. 
 pub fn ws_send_json(json: serde_json::Value, address: &'static str) {
-	let _ws_client = connect(address, move |out| {
+	connect(address, move |out| {
 		Client { 
-			out: out, 
+			out, 
 			json: json.clone() 
 		}
     }).expect("Error with Client");
@@ -8,7 +8,6 @@ pub mod utility;
 use crate::exchange::order_book::Book;
 use crate::order::TradeType;
 use crate::exchange::queue::Queue;
-use crate::controller::State;
 
 #[macro_use]
 extern crate serde_derive;
@@ -16,14 +15,14 @@ extern crate serde_derive;
 #[macro_use]
 extern crate serde_json;
 
-use std::sync::{Mutex, Arc};
+use std::sync::Arc;
 
 
-pub fn setup_exchange() -> (Arc<Queue>, Arc<Book>, Arc<Book>, Arc<Mutex<State>>) {
+pub fn setup_exchange() -> (Arc<Queue>, Arc<Book>, Arc<Book>) {
 	let queue = Arc::new(Queue::new());
 	let bids_book = Arc::new(Book::new(TradeType::Bid));
 	let asks_book = Arc::new(Book::new(TradeType::Ask));
-	(queue, bids_book, asks_book, Arc::new(Mutex::new(State::Process)))
+	(queue, bids_book, asks_book)
 }
 
 
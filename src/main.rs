@@ -5,6 +5,13 @@ use flow_rs::io::ws_json::ws_listener;
 use flow_rs::io::tcp_json::tcp_listener;
 use flow_rs::exchange::queue_processing::QueueProcessor;
 use flow_rs::exchange::auction::Auction;
+use flow_rs::exchange::settlement::Ledger;
+use flow_rs::exchange::events::EventQueue;
+use flow_rs::exchange::gateway::Gateway;
+use flow_rs::exchange::config::ExchangeConfig;
+use flow_rs::exchange::history::AuctionHistory;
+use flow_rs::exchange::scheduling::{Scheduler, FixedInterval};
+use flow_rs::exchange::self_trade::SelfTradeGuard;
 use flow_rs::controller::Controller;
 
 use std::sync::Arc;
@@ -13,16 +20,40 @@ use std::sync::Arc;
 
 fn main() {
 	// Initialize the Exchange
-	let (queue, bids_book, asks_book, state) = flow_rs::setup_exchange();
+	let (queue, bids_book, asks_book) = flow_rs::setup_exchange();
+
+	// Create the ledger that settles each auction's fills
+	let ledger = Arc::new(Ledger::new());
+
+	// Create the queue of events waiting to be delivered to traders
+	let events = Arc::new(EventQueue::new());
+
+	// Stop traders trading with themselves as their orders arrive
+	let guard = Arc::new(SelfTradeGuard::new(Arc::clone(&bids_book), Arc::clone(&asks_book), Arc::clone(&events)));
+
+	// Keep a history of every auction's results in memory
+	let history = Arc::new(AuctionHistory::new());
+
+	// Auctions clear with the default exchange settings
+	let config = Arc::new(ExchangeConfig::default());
 
 	// Create a new Controller to dispatch our tasks
 	let mut controller = Controller::new();
     
 	// create a task run an auction every batch_interval (milliseconds)
 	let batch_interval = 3000;
+	let scheduler = Arc::new(Scheduler::new(Box::new(FixedInterval::new(batch_interval)),
+		                                    Arc::clone(&queue),
+		                                    Arc::clone(&bids_book),
+		                                    Arc::clone(&asks_book)));
 	let auction_task = Auction::async_auction_task(Arc::clone(&bids_book), 
 		                          Arc::clone(&asks_book), 
-		                          Arc::clone(&state), batch_interval);
+		                          Arc::clone(&ledger),
+		                          Arc::clone(&events),
+		                          Arc::clone(&history),
+		                          Arc::clone(&config), 
+		                          Arc::clone(&scheduler),
+		                          Arc::clone(&guard));
 	controller.push(auction_task);
 
 	// create a task that processes order queue every queue_interval (milliseconds)
@@ -30,18 +61,25 @@ fn main() {
 	let queue_task = QueueProcessor::async_queue_task(Arc::clone(&queue), 
 		                                             Arc::clone(&bids_book), 
 		                                             Arc::clone(&asks_book),
-		                                             Arc::clone(&state),
+		                                             Arc::clone(&guard),
 		                                             queue_interval);
 	controller.push(queue_task);
 
+	// Orders and queries from traders reach the exchange through the gateway
+	let gateway = Arc::new(Gateway::new(Arc::clone(&queue), Arc::clone(&config), 
+	                                     Arc::clone(&events), Arc::clone(&scheduler),
+	                                     Arc::clone(&guard), Arc::clone(&history),
+	                                     Arc::clone(&ledger), Arc::clone(&bids_book),
+	                                     Arc::clone(&asks_book)));
+
 	// Spawn the tcp server task that listens for incoming orders in JSON format
-	let tcp_server = tcp_listener(Arc::clone(&queue), format!("127.0.0.1:5000"));
+	let tcp_server = tcp_listener(Arc::clone(&gateway), String::from("127.0.0.1:5000"));
 	controller.push(tcp_server);
 
 
 	// Spawn the websocket server thread that listens for incoming orders in JSON format
 	let address: &'static str = "127.0.0.1:3015";
-	let _ws_server = ws_listener(Arc::clone(&queue), &address);
+	let _ws_server = ws_listener(Arc::clone(&gateway), address);
 	
 	// Loop forever asynchronously running tasks
 	controller.run();
@@ -1,8 +1,11 @@
-type CustClosure = Box<Fn(f64) -> f64 + Send + Sync + 'static>;
+pub mod schedule;
 
+use crate::order::schedule::{Schedule, Monotonicity, ScheduleSpec};
 
-//TODO make an order trait with desired functions so that people
-// can reimplement their own functions as long as the output is the same.
+use std::sync::Arc;
+use std::time::Duration;
+
+pub type CustClosure = Box<dyn Fn(f64) -> f64 + Send + Sync + 'static>;
 
 
 
@@ -10,7 +13,8 @@ type CustClosure = Box<Fn(f64) -> f64 + Send + Sync + 'static>;
 
 
 /// Enum for matching over order types
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum OrderType {
     Enter,
     Update,
@@ -29,7 +33,8 @@ impl Clone for OrderType {
 
 
 // Enum for matching over bid or ask
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum TradeType {
     Bid,
     Ask,
@@ -44,42 +49,200 @@ impl Clone for TradeType {
 	}
 }
 
+/// How long an order stays in the Book before the exchange expires it. Serialized
+/// with a "type" tag, ie {"type": "good_for_auctions", "auctions": 5}.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum TimeInForce {
+	/// Stays in the Book until it is cancelled or trades its full quantity
+	#[default]
+	GoodTillCancel,
+	/// Expires after taking part in 'auctions' auctions
+	GoodForAuctions { auctions: u32 },
+	/// Expires at the first batch boundary at or after 'expires', in milliseconds since the UNIX epoch
+	GoodTillTime { expires: u64 },
+	/// Takes part in the next auction only
+	ImmediateOrCancel,
+}
+
 /// The internal data structure that the flow market operates on. 
-/// trader_id: String -> identifier of the trader and their order
+/// trader_id: String -> identifier of the trader
+/// order_id: Option<u64> -> exchange assigned identifier of the order, None until it is queued
+/// client_order_id: Option<String> -> trader's own identifier for the order, echoed back in acks
+/// seq: Option<u64> -> exchange wide sequence number stamped when the order arrived
+/// received: Option<Duration> -> time the exchange received the order
 /// order_type: OrderType{Enter, Update, Cancel} -> identifies how the order is used by the exchange
 /// trade_type: TradeType{Bid, Ask} -> decides which order book the order is placed in 
 /// p_low: f64 -> trader's low price
 /// p_high: f64 -> trader's high price
 /// u_max: f64 -> trader's max rate to trade
-/// function: Box<Fn(f64) -> f64> -> trader's custom closure that defines their demand/supply schedule
+/// quantity: Option<f64> -> total quantity to trade, None trades until cancelled
+/// remaining: Option<f64> -> quantity left to trade after previous auctions
+/// time_in_force: TimeInForce -> when the exchange expires the order
+/// auctions: u32 -> number of auctions the order has taken part in
+/// deadline: Option<u64> -> time to finish trading quantity by in milliseconds since the UNIX epoch, the
+/// exchange then manages u_max
+/// schedule: ScheduleSpec -> description of the trader's demand/supply schedule, evaluated on demand
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Order {
 	pub trader_id: String,		
+	pub order_id: Option<u64>,
+	pub client_order_id: Option<String>,
+	pub seq: Option<u64>,
+	pub received: Option<Duration>,
 	pub order_type: OrderType,	
 	pub trade_type: TradeType,  
 	pub p_low: f64,				
 	pub p_high: f64,			
 	pub u_max: f64,				
-	function: CustClosure,	    
+	pub quantity: Option<f64>,
+	pub remaining: Option<f64>,
+	pub time_in_force: TimeInForce,
+	pub auctions: u32,
+	pub deadline: Option<u64>,
+	pub schedule: ScheduleSpec,
 }
 
 impl Order {
     pub fn new(t_id: String, o_t: OrderType, t_t: TradeType, pl: f64, ph: f64, u: f64,
-    	function: CustClosure) -> Order 
+    	schedule: ScheduleSpec) -> Order 
     {
     	Order {
     		trader_id: t_id,		
+			order_id: None,
+			client_order_id: None,
+			seq: None,
+			received: None,
 			order_type: o_t,	
 			trade_type: t_t,  
 			p_low: pl,				
 			p_high: ph,	
 			u_max: u,		
-			function,			
+			quantity: None,
+			remaining: None,
+			time_in_force: TimeInForce::GoodTillCancel,
+			auctions: 0,
+			deadline: None,
+			schedule,
+    	}
+    }
+
+    /// A step order that bids u_max at or below 'price', or asks u_max at or above it.
+    /// Its schedule is piecewise linear with p_low == p_high, so it's vertical at 'price'.
+    pub fn limit(t_id: String, o_t: OrderType, t_t: TradeType, price: f64, u: f64) -> Order {
+    	Order::new(t_id, o_t, t_t, price, price, u, ScheduleSpec::PiecewiseLinear)
+    }
+
+    /// True if the order's schedule is a single step at its limit price
+    pub fn is_limit(&self) -> bool {
+    	self.p_low == self.p_high
+    }
+
+    /// Sets the total quantity the order will trade before it is exhausted
+    pub fn with_quantity(mut self, quantity: f64) -> Order {
+    	self.quantity = Some(quantity);
+    	self.remaining = Some(quantity);
+    	self
+    }
+
+    /// Sets how long the order stays in the Book
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Order {
+    	self.time_in_force = time_in_force;
+    	self
+    }
+
+    /// True once the order's time in force has run out at time 'now'
+    pub fn is_expired(&self, now: Duration) -> bool {
+    	match self.time_in_force {
+    		TimeInForce::GoodTillCancel => false,
+    		TimeInForce::GoodForAuctions { auctions } => self.auctions >= auctions,
+    		TimeInForce::GoodTillTime { expires } => now.as_millis() as u64 >= expires,
+    		TimeInForce::ImmediateOrCancel => self.auctions >= 1,
+    	}
+    }
+
+    /// True if the order's time in force depends on how many auctions it has taken part in
+    pub fn counts_auctions(&self) -> bool {
+    	matches!(self.time_in_force, TimeInForce::GoodForAuctions { .. } | TimeInForce::ImmediateOrCancel)
+    }
+
+    /// Makes the exchange trade the order's quantity by 'deadline', in milliseconds since
+    /// the UNIX epoch, rewriting u_max each batch. The order needs a total quantity.
+    pub fn with_deadline(mut self, deadline: u64) -> Order {
+    	self.deadline = Some(deadline);
+    	self
+    }
+
+    /// The quantity traded so far, zero for orders without a total quantity
+    pub fn filled(&self) -> f64 {
+    	match (self.quantity, self.remaining) {
+    		(Some(q), Some(r)) => q - r,
+    		_ => 0.0,
     	}
     }
 
-    // method for calling the order's closure
+    /// Rewrites u_max so the remaining quantity is spread evenly over the time left until
+    /// the deadline at time 'now'. 'batch' is the expected batch length in milliseconds,
+    /// and anything left with less than a batch to go is spread over one batch.
+    /// Orders without a deadline are unchanged.
+    pub fn pace(&mut self, now: Duration, batch: u64) {
+    	if let (Some(deadline), Some(remaining)) = (self.deadline, self.remaining) {
+    		let left = deadline.saturating_sub(now.as_millis() as u64).max(batch).max(1);
+    		self.u_max = remaining / (left as f64 / 1000.0);
+    	}
+    }
+
+    /// Carries what the exchange has tracked on 'old', the order an update replaces,
+    /// over to this order at time 'now': the quantity it has traded and the auctions it
+    /// has taken part in. The update keeps its own seq, which its acknowledgement reported.
+    /// A new quantity is a new total, so only what it leaves after the fills so far
+    /// remains, and a new time in force counts auctions from zero. Deadline orders are
+    /// paced again from what remains.
+    pub fn carry_over(&mut self, old: &Order, now: Duration) {
+    	if let Some(q) = self.quantity {
+    		self.remaining = Some((q - old.filled()).max(0.0));
+    	}
+    	if self.time_in_force == old.time_in_force {
+    		self.auctions = old.auctions;
+    	}
+    	self.pace(now, 0);
+    }
+
+    /// Sets the trader's own identifier for the order
+    pub fn with_client_order_id(mut self, client_order_id: String) -> Order {
+    	self.client_order_id = Some(client_order_id);
+    	self
+    }
+
+    /// Decrements the remaining quantity by 'filled' and returns true once the 
+    /// order is exhausted. Orders without a total quantity are never exhausted.
+    pub fn deplete(&mut self, filled: f64) -> bool {
+    	match self.remaining {
+    		Some(r) => {
+    			let left = (r - filled).max(0.0);
+    			self.remaining = Some(left);
+    			left <= 0.0
+    		},
+    		None => false,
+    	}
+    }
+
+    /// Creates an order from a user defined schedule. The order's prices and max
+    /// rate come from the schedule, and it is a Bid if the schedule is non-increasing
+    /// or an Ask if it is non-decreasing. The schedule is held as ScheduleSpec::Custom,
+    /// so the order is evaluated exactly but can't be serialized.
+    pub fn from_schedule<S: Schedule + 'static>(t_id: String, o_t: OrderType, schedule: S) -> Order {
+    	let t_t = match schedule.monotonicity() {
+    		Monotonicity::NonIncreasing => TradeType::Bid,
+    		Monotonicity::NonDecreasing => TradeType::Ask,
+    	};
+    	let (pl, ph, u) = (schedule.p_low(), schedule.p_high(), schedule.u_max());
+    	Order::new(t_id, o_t, t_t, pl, ph, u, ScheduleSpec::Custom(Arc::new(schedule)))
+    }
+
+    // method for evaluating the order's schedule
     pub fn calculate(&self, arg: f64) -> f64 {
-    	(self.function)(arg)
+    	self.schedule.evaluate(&self.trade_type, self.p_low, self.p_high, self.u_max, arg)
     }
 
     pub fn describe(&self) {
@@ -90,58 +253,85 @@ impl Order {
     }
 }
 
+impl Schedule for Order {
+	fn evaluate(&self, p: f64) -> f64 {
+		self.calculate(p)
+	}
+
+	fn p_low(&self) -> f64 {
+		self.p_low
+	}
+
+	fn p_high(&self) -> f64 {
+		self.p_high
+	}
+
+	fn u_max(&self) -> f64 {
+		self.u_max
+	}
+
+	// Bids demand less as the price rises and asks supply more
+	fn monotonicity(&self) -> Monotonicity {
+		match self.trade_type {
+			TradeType::Bid => Monotonicity::NonIncreasing,
+			TradeType::Ask => Monotonicity::NonDecreasing,
+		}
+	}
+}
+
 
 	/// Creates a closure from an array of floats. This closure is the 
 	/// equivalent to a polynomial. 
 	/// For example: coef = [3, 5, 4, 1] => 3x^3 + 5x^2 + 4x + 1
     pub fn poly_clos_from_coef(coefs: Vec<f64>) -> CustClosure {
         // let x be a generic f64 input that closure will compute on
-        let iter = Box::new(move |x: f64| -> f64 {
-        	// rev since enumerate counts from 0 up, and we wish
-        	// to extract out the index which corresponds to the poly's
-        	// degree.
-        	coefs.iter().rev().enumerate()
-        	    .map(|(deg, coef)| {
-        	    	// deg = index in rev order
-        	    	// coef = poly's coef from vector
-        	    	let eval: f64 = coef * x.powi(deg as i32);
-        	    	eval
-        	    })
-        	    .sum()
-        });
-        iter
+        Box::new(move |x: f64| -> f64 { eval_poly(&coefs, x) })
     }
 
     /// Creates a piecewise linear demand schedule closure from the 
     /// p_low, p_high, and u_max parameters. The input 'x: f64' to the closure
     /// is the price and the output is the shares at that price.
     pub fn p_wise_dem(p_l: f64, p_h: f64, u: f64) -> CustClosure {
-    	let func = Box::new(move |x: f64| -> f64 {
-    		if x <= p_l {
-	    		u
-	    	} else if x > p_h {
-	    		0.0
-	    	} else {
-	    		u * ((p_h - x) / (p_h - p_l))
-	    	}
-    	});
-    	func
+    	Box::new(move |x: f64| -> f64 { eval_p_wise_dem(p_l, p_h, u, x) })
     }
 
     /// Creates a piecewise linear supply schedule closure from the 
     /// p_low, p_high, and u_max parameters. The input 'x: f64' to the closure
     /// is the price and the output is the shares at that price.
     pub fn p_wise_sup(p_l: f64, p_h: f64, u: f64) -> CustClosure {
-    	let func = Box::new(move |x: f64| -> f64 {
-    		if x < p_l {
-	    		0.0
-	    	} else if x >= p_h {
-	    		u
-	    	} else {
-	    		u + ((x - p_h) / (p_h - p_l)) * u
-	    	}
-    	});
-    	func
+    	Box::new(move |x: f64| -> f64 { eval_p_wise_sup(p_l, p_h, u, x) })
+    }
+
+    // Evaluates the polynomial with coefficients from the highest degree down at x
+    fn eval_poly(coefs: &[f64], x: f64) -> f64 {
+    	// rev since enumerate counts from 0 up, and we wish
+    	// to extract out the index which corresponds to the poly's
+    	// degree.
+    	coefs.iter().rev().enumerate()
+    	    .map(|(deg, coef)| coef * x.powi(deg as i32))
+    	    .sum()
+    }
+
+    // Evaluates the piecewise linear demand schedule at x
+    fn eval_p_wise_dem(p_l: f64, p_h: f64, u: f64, x: f64) -> f64 {
+		if x <= p_l {
+    		u
+    	} else if x > p_h {
+    		0.0
+    	} else {
+    		u * ((p_h - x) / (p_h - p_l))
+    	}
+    }
+
+    // Evaluates the piecewise linear supply schedule at x
+    fn eval_p_wise_sup(p_l: f64, p_h: f64, u: f64, x: f64) -> f64 {
+		if x < p_l {
+    		0.0
+    	} else if x >= p_h {
+    		u
+    	} else {
+    		u + ((x - p_h) / (p_h - p_l)) * u
+    	}
     }
 
 
@@ -158,11 +348,8 @@ mod tests {
 			0.0,
 			100.0,
 			500.0,
-			Box::new(|x| {
-				println!("This is my closure");
-				x + 1 as f64
-			})
-
+			// x + 1
+			ScheduleSpec::Polynomial { coefs: vec![1.0, 1.0] },
 		);
 
 		assert_eq!(order.trader_id, "trader_id");
@@ -171,6 +358,77 @@ mod tests {
 		assert_eq!(order.p_low, 0.0);
 		assert_eq!(order.p_high, 100.0);
 		assert_eq!(order.calculate(5.0), 6.0);
+		assert_eq!(order.quantity, None);
+	}
+
+	#[test]
+	fn test_order_deplete() {
+		let mut order = Order::new(
+			String::from("trader_id"),
+			OrderType::Enter,
+			TradeType::Ask,
+			0.0,
+			100.0,
+			500.0,
+			ScheduleSpec::PiecewiseLinear,
+		).with_quantity(10.0);
+
+		assert_eq!(order.remaining, Some(10.0));
+		assert!(!order.deplete(4.0));
+		assert_eq!(order.remaining, Some(6.0));
+		assert!(order.deplete(6.0));
+		assert_eq!(order.remaining, Some(0.0));
+		assert_eq!(order.quantity, Some(10.0));
+	}
+
+	#[test]
+	fn test_order_from_schedule() {
+		// A concave demand curve: u_max * (1 - ((p - p_low) / (p_high - p_low))^2)
+		struct ConcaveDemand {
+			p_low: f64,
+			p_high: f64,
+			u_max: f64,
+		}
+
+		impl Schedule for ConcaveDemand {
+			fn evaluate(&self, p: f64) -> f64 {
+				let x = (p - self.p_low) / (self.p_high - self.p_low);
+				self.u_max * (1.0 - x * x)
+			}
+			fn p_low(&self) -> f64 { self.p_low }
+			fn p_high(&self) -> f64 { self.p_high }
+			fn u_max(&self) -> f64 { self.u_max }
+			fn monotonicity(&self) -> Monotonicity { Monotonicity::NonIncreasing }
+		}
+
+		let order = Order::from_schedule(String::from("trader_id"), OrderType::Enter,
+			ConcaveDemand { p_low: 0.0, p_high: 10.0, u_max: 100.0 });
+
+		assert_eq!(order.trade_type, TradeType::Bid);
+		assert_eq!(order.p_high, 10.0);
+		assert_eq!(order.u_max, 100.0);
+		assert_eq!(order.rate(-1.0), 100.0);
+		// Evaluated exactly between the prices it would once have been sampled at
+		assert_eq!(order.rate(5.0), 75.0);
+		assert_eq!(order.rate(3.3), 100.0 * (1.0 - 0.33 * 0.33));
+		assert_eq!(order.clone().schedule, order.schedule);
+		assert!(order.schedule.validate(&order.trade_type, 0.0, 10.0, 100.0).is_ok());
+		assert!(serde_json::to_value(&order).is_err());
+		assert_eq!(order.rate(0.0), 100.0);
+		assert_eq!(order.rate(10.0), 0.0);
+		assert_eq!(order.rate(11.0), 0.0);
+	}
+
+	#[test]
+	fn test_order_rate_is_clipped() {
+		// -3x + 4 goes negative inside [0, 100]
+		let order = Order::new(String::from("trader_id"), OrderType::Enter, TradeType::Ask,
+			0.0, 100.0, 500.0, ScheduleSpec::Polynomial { coefs: vec![-3.0, 4.0] });
+		assert_eq!(order.monotonicity(), Monotonicity::NonDecreasing);
+		assert_eq!(order.rate(1.0), 1.0);
+		assert_eq!(order.rate(50.0), 0.0);
+		assert_eq!(order.rate(-1.0), 0.0);
+		assert_eq!(order.rate(100.0), 500.0);
 	}
 
 	#[test]
@@ -187,8 +445,6 @@ mod tests {
 		//x=4: 192+ 64 + 20 + 1 = 277
 
 		// -3x + 4
-		let closure = poly_clos_from_coef(vec![-3.0, 4.0]);
-
 		let order = Order::new(
 			String::from("trader_id"),
 			OrderType::Enter,
@@ -196,7 +452,7 @@ mod tests {
 			0.0,
 			100.0,
 			500.0,
-			closure
+			ScheduleSpec::Polynomial { coefs: vec![-3.0, 4.0] },
 		);
 
 		assert_eq!(-17.0, order.calculate(7.0));
@@ -224,8 +480,22 @@ mod tests {
 		assert_eq!(closure(150.0), 250.0);
 		assert_eq!(closure(300.0), 500.0);
 	}
-}
 
+	#[test]
+	fn test_limit_order() {
+		let bid = Order::limit(String::from("trader_id"), OrderType::Enter, TradeType::Bid, 100.0, 500.0);
+		assert!(bid.is_limit());
+		assert_eq!(bid.rate(99.0), 500.0);
+		assert_eq!(bid.rate(100.0), 500.0);
+		assert_eq!(bid.rate(100.5), 0.0);
+
+		let ask = Order::limit(String::from("trader_id"), OrderType::Enter, TradeType::Ask, 100.0, 500.0);
+		assert_eq!(ask.rate(99.5), 0.0);
+		assert_eq!(ask.rate(100.0), 500.0);
+		assert_eq!(ask.rate(101.0), 500.0);
+		assert!(!ask.rate(100.0).is_nan());
+	}
+}
 
 
 
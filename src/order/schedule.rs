@@ -0,0 +1,419 @@
+use crate::order::{TradeType, eval_poly, eval_p_wise_dem, eval_p_wise_sup};
+
+use std::fmt;
+use std::sync::Arc;
+
+
+/// Number of prices a schedule is sampled at when its shape can't be checked analytically
+pub const SHAPE_SAMPLES: usize = 1000;
+
+/// Tolerance for rounding error when checking the shape of a schedule
+const SHAPE_TOLERANCE: f64 = 1e-9;
+
+/// The direction a schedule moves in as the price rises. Demand schedules
+/// must never increase with price and supply schedules must never decrease.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Monotonicity {
+	NonIncreasing,
+	NonDecreasing,
+}
+
+impl Monotonicity {
+	/// Bids demand and must be non-increasing, asks supply and must be non-decreasing
+	pub fn of(trade_type: &TradeType) -> Monotonicity {
+		match trade_type {
+			TradeType::Bid => Monotonicity::NonIncreasing,
+			TradeType::Ask => Monotonicity::NonDecreasing,
+		}
+	}
+}
+
+/// A demand or supply schedule that maps a price to a rate of trade. Implement
+/// this to trade with a custom curve, ie a concave or convex demand curve, then
+/// wrap it in an Order with Order::from_schedule.
+///
+/// evaluate() only needs to be correct on [p_low, p_high]. Outside of that range
+/// rate() treats a non-increasing schedule as demanding u_max below p_low and
+/// nothing above p_high, and a non-decreasing schedule as supplying nothing below
+/// p_low and u_max above p_high.
+pub trait Schedule: Send + Sync {
+	/// The schedule's rate at price p on [p_low, p_high]
+	fn evaluate(&self, p: f64) -> f64;
+
+	/// The lowest price the schedule bends at
+	fn p_low(&self) -> f64;
+
+	/// The highest price the schedule bends at
+	fn p_high(&self) -> f64;
+
+	/// The maximum rate the schedule trades at
+	fn u_max(&self) -> f64;
+
+	/// Whether the schedule is a demand (non-increasing) or supply (non-decreasing) curve
+	fn monotonicity(&self) -> Monotonicity;
+
+	/// The schedule's rate at any price. Below p_low a demand schedule trades u_max and
+	/// a supply schedule nothing, above p_high the reverse. Inside [p_low, p_high] the
+	/// schedule is clipped to [0, u_max].
+	fn rate(&self, p: f64) -> f64 {
+		match self.monotonicity() {
+			Monotonicity::NonIncreasing => {
+				if p <= self.p_low() {
+					self.u_max()
+				} else if p > self.p_high() {
+					0.0
+				} else {
+					self.evaluate(p).max(0.0).min(self.u_max())
+				}
+			},
+			Monotonicity::NonDecreasing => {
+				if p < self.p_low() {
+					0.0
+				} else if p >= self.p_high() {
+					self.u_max()
+				} else {
+					self.evaluate(p).max(0.0).min(self.u_max())
+				}
+			},
+		}
+	}
+}
+
+impl fmt::Debug for dyn Schedule {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Schedule {{ p_low: {}, p_high: {}, u_max: {}, monotonicity: {:?} }}",
+			self.p_low(), self.p_high(), self.u_max(), self.monotonicity())
+	}
+}
+
+/// User defined schedules can't be compared by value, so they are equal only
+/// when they are the same schedule
+impl PartialEq for dyn Schedule {
+	fn eq(&self, other: &dyn Schedule) -> bool {
+		std::ptr::eq(self as *const dyn Schedule as *const u8, other as *const dyn Schedule as *const u8)
+	}
+}
+
+/// A description of a schedule that is stored in an Order and evaluated on demand,
+/// so orders can be cloned, sent over the wire and journaled. Serialized with a "type" tag, ie
+/// {"type": "polynomial", "coefs": [-3.0, 4.0]}. Schedules are evaluated on the
+/// order's [p_low, p_high] and clipped to [0, u_max]. User defined schedules are
+/// held as Custom, which is evaluated exactly but can't be sent over the wire.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ScheduleSpec {
+	/// Linear from u_max at p_low to 0 at p_high for bids, the reverse for asks
+	PiecewiseLinear,
+	/// Linear between a list of [price, rate] points sorted by price
+	Breakpoints { points: Vec<(f64, f64)> },
+	/// A list of [price, rate] steps sorted by price, the rate at p is that of the 
+	/// last step at or below p
+	Step { steps: Vec<(f64, f64)> },
+	/// Polynomial coefficients from the highest degree down, see poly_clos_from_coef
+	Polynomial { coefs: Vec<f64> },
+	/// rate = scale * p^elasticity, elasticity is negative for demand
+	ConstantElasticity { scale: f64, elasticity: f64 },
+	/// A user defined schedule, see Order::from_schedule. Never serialized, so
+	/// orders holding one can't be sent to the exchange as JSON.
+	#[serde(skip)]
+	Custom(Arc<dyn Schedule>),
+}
+
+impl ScheduleSpec {
+	/// Checks the exchange can evaluate the schedule for an order with the supplied
+	/// p_low, or returns the reason it can't.
+	pub fn check(&self, p_low: f64) -> Result<(), &'static str> {
+		match self {
+			ScheduleSpec::PiecewiseLinear => Ok(()),
+			ScheduleSpec::Breakpoints { points } => {
+				check_points(points)?;
+				if points.len() < 2 {
+					return Err("breakpoint schedules need at least two points");
+				}
+				Ok(())
+			},
+			ScheduleSpec::Step { steps } => {
+				check_points(steps)?;
+				if steps.is_empty() {
+					return Err("step schedules need at least one step");
+				}
+				Ok(())
+			},
+			ScheduleSpec::Polynomial { coefs } => {
+				if coefs.is_empty() {
+					return Err("polynomial schedules need at least one coefficient");
+				}
+				if coefs.iter().any(|c| !c.is_finite()) {
+					return Err("polynomial coefficients must be finite");
+				}
+				Ok(())
+			},
+			ScheduleSpec::ConstantElasticity { scale, elasticity } => {
+				if !scale.is_finite() || !elasticity.is_finite() {
+					return Err("constant elasticity parameters must be finite");
+				}
+				if p_low <= 0.0 {
+					return Err("constant elasticity schedules need positive prices");
+				}
+				Ok(())
+			},
+			ScheduleSpec::Custom(_) => Ok(()),
+		}
+	}
+
+	/// The prices the schedule bends or steps at besides an order's p_low and p_high,
+	/// or None if it isn't piecewise linear between them, ie a polynomial.
+	pub fn breakpoints(&self) -> Option<Vec<f64>> {
+		match self {
+			ScheduleSpec::PiecewiseLinear => Some(Vec::new()),
+			ScheduleSpec::Breakpoints { points } => Some(points.iter().map(|(p, _)| *p).collect()),
+			ScheduleSpec::Step { steps } => Some(steps.iter().map(|(p, _)| *p).collect()),
+			ScheduleSpec::Polynomial { .. }
+			| ScheduleSpec::ConstantElasticity { .. }
+			| ScheduleSpec::Custom(_) => None,
+		}
+	}
+
+	/// Evaluates the schedule at price x for an order with the supplied parameters.
+	/// The result isn't clipped, see Schedule::rate for that.
+	pub fn evaluate(&self, trade_type: &TradeType, p_low: f64, p_high: f64, u_max: f64, x: f64) -> f64 {
+		match self {
+			ScheduleSpec::PiecewiseLinear => match trade_type {
+				TradeType::Bid => eval_p_wise_dem(p_low, p_high, u_max, x),
+				TradeType::Ask => eval_p_wise_sup(p_low, p_high, u_max, x),
+			},
+			ScheduleSpec::Breakpoints { points } => {
+				if points.is_empty() {
+					return 0.0;
+				}
+				match points.iter().position(|(p, _)| *p >= x) {
+					None => points[points.len() - 1].1,
+					Some(0) => points[0].1,
+					Some(i) => {
+						let (p0, r0) = points[i - 1];
+						let (p1, r1) = points[i];
+						if p1 == p0 { r1 } else { r0 + (r1 - r0) * (x - p0) / (p1 - p0) }
+					},
+				}
+			},
+			ScheduleSpec::Step { steps } => {
+				steps.iter().rev()
+					.find(|(p, _)| *p <= x)
+					.or_else(|| steps.first())
+					.map_or(0.0, |(_, r)| *r)
+			},
+			ScheduleSpec::Polynomial { coefs } => eval_poly(coefs, x),
+			ScheduleSpec::ConstantElasticity { scale, elasticity } => scale * x.powf(*elasticity),
+			ScheduleSpec::Custom(schedule) => schedule.evaluate(x),
+		}
+	}
+}
+
+impl ScheduleSpec {
+	/// Checks the schedule has a valid shape for an order with the supplied parameters:
+	/// finite, within [0, u_max] on [p_low, p_high], non-increasing for bids and
+	/// non-decreasing for asks. Forms with a known shape are checked analytically and
+	/// polynomials and user defined schedules are sampled. Specs should first be checked with check. Returns
+	/// the reason the schedule was rejected.
+	pub fn validate(&self, trade_type: &TradeType, p_low: f64, p_high: f64, u_max: f64) 
+	-> Result<(), &'static str> 
+	{
+		let monotonicity = Monotonicity::of(trade_type);
+		match self {
+			ScheduleSpec::PiecewiseLinear => Ok(()),
+			ScheduleSpec::Breakpoints { points } => check_point_shape(points, monotonicity, u_max),
+			ScheduleSpec::Step { steps } => check_point_shape(steps, monotonicity, u_max),
+			ScheduleSpec::ConstantElasticity { scale, elasticity } => {
+				if *scale < 0.0 {
+					return Err("schedule rate is negative");
+				}
+				let (wrong_way, peak) = match monotonicity {
+					Monotonicity::NonIncreasing => (*elasticity > 0.0, p_low),
+					Monotonicity::NonDecreasing => (*elasticity < 0.0, p_high),
+				};
+				if wrong_way && *scale > 0.0 {
+					return Err(wrong_direction(monotonicity));
+				}
+				if scale * peak.powf(*elasticity) > u_max + SHAPE_TOLERANCE {
+					return Err("schedule rate exceeds u_max");
+				}
+				Ok(())
+			},
+			ScheduleSpec::Polynomial { .. } | ScheduleSpec::Custom(_) => {
+				let func = |x: f64| self.evaluate(trade_type, p_low, p_high, u_max, x);
+				check_shape(&func, monotonicity, p_low, p_high, u_max)
+			},
+		}
+	}
+}
+
+/// Checks the shape of an arbitrary schedule by sampling it at SHAPE_SAMPLES prices
+/// across [p_low, p_high]. Returns the reason the schedule was rejected.
+pub fn check_shape(func: &dyn Fn(f64) -> f64, monotonicity: Monotonicity, p_low: f64, p_high: f64, u_max: f64) 
+-> Result<(), &'static str> 
+{
+	let step = (p_high - p_low) / (SHAPE_SAMPLES - 1) as f64;
+	let mut last: Option<f64> = None;
+	for i in 0..SHAPE_SAMPLES {
+		let rate = func(p_low + step * i as f64);
+		check_rate(rate, u_max)?;
+		if let Some(prev) = last {
+			if moves_wrong_way(prev, rate, monotonicity) {
+				return Err(wrong_direction(monotonicity));
+			}
+		}
+		last = Some(rate);
+	}
+	Ok(())
+}
+
+// Checks the rates of a list of [price, rate] points, which bound a schedule built from them
+fn check_point_shape(points: &[(f64, f64)], monotonicity: Monotonicity, u_max: f64) -> Result<(), &'static str> {
+	for (_, rate) in points.iter() {
+		check_rate(*rate, u_max)?;
+	}
+	if points.windows(2).any(|w| moves_wrong_way(w[0].1, w[1].1, monotonicity)) {
+		return Err(wrong_direction(monotonicity));
+	}
+	Ok(())
+}
+
+fn check_rate(rate: f64, u_max: f64) -> Result<(), &'static str> {
+	if !rate.is_finite() {
+		Err("schedule rate is not finite")
+	} else if rate < -SHAPE_TOLERANCE {
+		Err("schedule rate is negative")
+	} else if rate > u_max + SHAPE_TOLERANCE {
+		Err("schedule rate exceeds u_max")
+	} else {
+		Ok(())
+	}
+}
+
+fn moves_wrong_way(prev: f64, next: f64, monotonicity: Monotonicity) -> bool {
+	match monotonicity {
+		Monotonicity::NonIncreasing => next > prev + SHAPE_TOLERANCE,
+		Monotonicity::NonDecreasing => next < prev - SHAPE_TOLERANCE,
+	}
+}
+
+fn wrong_direction(monotonicity: Monotonicity) -> &'static str {
+	match monotonicity {
+		Monotonicity::NonIncreasing => "bid schedule increases with price",
+		Monotonicity::NonDecreasing => "ask schedule decreases with price",
+	}
+}
+
+// Checks a list of [price, rate] points is finite and sorted by price
+fn check_points(points: &[(f64, f64)]) -> Result<(), &'static str> {
+	if points.iter().any(|(p, r)| !p.is_finite() || !r.is_finite()) {
+		return Err("schedule points must be finite");
+	}
+	if points.windows(2).any(|w| w[1].0 < w[0].0) {
+		return Err("schedule points must be sorted by price");
+	}
+	Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_spec_evaluate() {
+		let tt = TradeType::Bid;
+		assert_eq!(ScheduleSpec::PiecewiseLinear.evaluate(&tt, 100.0, 200.0, 500.0, 150.0), 250.0);
+
+		let points = ScheduleSpec::Breakpoints { points: vec![(0.0, 10.0), (5.0, 8.0), (10.0, 0.0)] };
+		assert_eq!(points.evaluate(&tt, 0.0, 10.0, 10.0, 2.5), 9.0);
+		assert_eq!(points.evaluate(&tt, 0.0, 10.0, 10.0, 7.5), 4.0);
+		assert_eq!(points.evaluate(&tt, 0.0, 10.0, 10.0, 20.0), 0.0);
+
+		let steps = ScheduleSpec::Step { steps: vec![(0.0, 10.0), (5.0, 4.0), (8.0, 0.0)] };
+		assert_eq!(steps.evaluate(&tt, 0.0, 10.0, 10.0, 4.9), 10.0);
+		assert_eq!(steps.evaluate(&tt, 0.0, 10.0, 10.0, 5.0), 4.0);
+		assert_eq!(steps.evaluate(&tt, 0.0, 10.0, 10.0, 9.0), 0.0);
+
+		let poly = ScheduleSpec::Polynomial { coefs: vec![-3.0, 4.0] };
+		assert_eq!(poly.evaluate(&tt, 0.0, 1.0, 5.0, 1.0), 1.0);
+
+		let elastic = ScheduleSpec::ConstantElasticity { scale: 100.0, elasticity: -1.0 };
+		assert_eq!(elastic.evaluate(&tt, 1.0, 10.0, 100.0, 4.0), 25.0);
+	}
+
+	#[test]
+	fn test_spec_breakpoints() {
+		assert_eq!(ScheduleSpec::PiecewiseLinear.breakpoints(), Some(vec![]));
+		let steps = ScheduleSpec::Step { steps: vec![(0.0, 10.0), (5.0, 4.0)] };
+		assert_eq!(steps.breakpoints(), Some(vec![0.0, 5.0]));
+		assert_eq!(ScheduleSpec::Polynomial { coefs: vec![1.0] }.breakpoints(), None);
+	}
+
+	#[test]
+	fn test_reject_unevaluable_specs() {
+		let unsorted = ScheduleSpec::Breakpoints { points: vec![(5.0, 1.0), (0.0, 0.0)] };
+		assert!(unsorted.check(0.0).is_err());
+
+		let single = ScheduleSpec::Breakpoints { points: vec![(5.0, 1.0)] };
+		assert!(single.check(0.0).is_err());
+
+		let empty = ScheduleSpec::Step { steps: vec![] };
+		assert!(empty.check(0.0).is_err());
+
+		let nan = ScheduleSpec::Polynomial { coefs: vec![f64::NAN] };
+		assert!(nan.check(0.0).is_err());
+
+		let elastic = ScheduleSpec::ConstantElasticity { scale: 1.0, elasticity: 0.5 };
+		assert!(elastic.check(0.0).is_err());
+		assert!(elastic.check(1.0).is_ok());
+	}
+
+	#[test]
+	fn test_validate_shapes() {
+		let (bid, ask) = (TradeType::Bid, TradeType::Ask);
+		assert!(ScheduleSpec::PiecewiseLinear.validate(&bid, 0.0, 10.0, 5.0).is_ok());
+
+		let rising = ScheduleSpec::Breakpoints { points: vec![(0.0, 1.0), (10.0, 5.0)] };
+		assert!(rising.validate(&ask, 0.0, 10.0, 5.0).is_ok());
+		assert_eq!(rising.validate(&bid, 0.0, 10.0, 5.0), Err("bid schedule increases with price"));
+		assert_eq!(rising.validate(&ask, 0.0, 10.0, 4.0), Err("schedule rate exceeds u_max"));
+
+		let steps = ScheduleSpec::Step { steps: vec![(0.0, 5.0), (5.0, -1.0)] };
+		assert_eq!(steps.validate(&bid, 0.0, 10.0, 5.0), Err("schedule rate is negative"));
+
+		// -x^2 + 10 falls on [0, 3] but goes negative by 4
+		let poly = ScheduleSpec::Polynomial { coefs: vec![-1.0, 0.0, 10.0] };
+		assert!(poly.validate(&bid, 0.0, 3.0, 10.0).is_ok());
+		assert_eq!(poly.validate(&bid, 0.0, 4.0, 10.0), Err("schedule rate is negative"));
+		assert_eq!(poly.validate(&ask, 0.0, 3.0, 10.0), Err("ask schedule decreases with price"));
+
+		let elastic = ScheduleSpec::ConstantElasticity { scale: 100.0, elasticity: -1.0 };
+		assert!(elastic.validate(&bid, 10.0, 20.0, 10.0).is_ok());
+		assert_eq!(elastic.validate(&bid, 5.0, 20.0, 10.0), Err("schedule rate exceeds u_max"));
+		assert_eq!(elastic.validate(&ask, 10.0, 20.0, 10.0), Err("ask schedule decreases with price"));
+
+		let blows_up = |x: f64| 1.0 / x;
+		assert_eq!(check_shape(&blows_up, Monotonicity::NonIncreasing, 0.0, 1.0, 10.0), Err("schedule rate is not finite"));
+	}
+
+	#[test]
+	fn test_spec_json() {
+		let json = serde_json::json!({"type": "breakpoints", "points": [[0.0, 10.0], [10.0, 0.0]]});
+		let spec: ScheduleSpec = serde_json::from_value(json).unwrap();
+		assert_eq!(spec, ScheduleSpec::Breakpoints { points: vec![(0.0, 10.0), (10.0, 0.0)] });
+
+		let json = serde_json::to_value(&ScheduleSpec::PiecewiseLinear).unwrap();
+		assert_eq!(json, serde_json::json!({"type": "piecewise_linear"}));
+
+		let unknown = serde_json::json!({"type": "spline", "knots": []});
+		assert!(serde_json::from_value::<ScheduleSpec>(unknown).is_err());
+
+		// User defined schedules only exist in process
+		let custom = serde_json::json!({"type": "custom"});
+		assert!(serde_json::from_value::<ScheduleSpec>(custom).is_err());
+		let order = crate::order::Order::new(String::from("trader_id"), crate::order::OrderType::Enter,
+			TradeType::Bid, 0.0, 10.0, 5.0, ScheduleSpec::PiecewiseLinear);
+		assert!(serde_json::to_value(ScheduleSpec::Custom(Arc::new(order))).is_err());
+	}
+}
@@ -1,7 +1,6 @@
 use crate::simulation::trader_behavior;
 use crate::order::{Order};
 use crate::simulation::trader::Traders;
-use crate::exchange::order_processing::JsonOrder;
 use crate::controller::Task;
 use crate::io::tcp_json;
 use crate::io::ws_json;
@@ -23,8 +22,7 @@ impl RandBehavior {
 
 	            // Send them over JSON
 	            for order in &orders {
-	                // Don't want a full clone of the order, just params to make json
-	                let json_order = JsonOrder::order_to_json(order);
+	                let json_order = serde_json::to_value(order).expect("Couldn't serialize order");
 	                // Spawn the task to send json over tcp
 	                let json_send_task = tcp_json::tcp_send_json(json_order, address.clone()).task;
                     tokio::spawn(json_send_task);
@@ -42,7 +40,7 @@ impl RandBehavior {
             let update_orders = trader_behavior::gen_rand_updates(Arc::clone(&traders), rng_upper);
             println!("updating {} traders", update_orders.len());
             for order in update_orders {
-            	let json_order = JsonOrder::params_to_json(order);
+            	let json_order = serde_json::to_value(&order).expect("Couldn't serialize order");
                 let json_send_task = tcp_json::tcp_send_json(json_order, address.clone()).task;
                 tokio::spawn(json_send_task);
             }
@@ -57,11 +55,11 @@ impl RandBehavior {
             let cancel_orders = trader_behavior::gen_rand_cancels(Arc::clone(&traders), rng_upper);
             println!("cancelling {} traders", cancel_orders.len());
             for order in cancel_orders {
-                println!("time: {:?}, cancelling: {:?} ", get_time(), order.0);
+                println!("time: {:?}, cancelling: {:?} ", get_time(), order.trader_id);
                 let addr = address.clone();
                 // Send a cancel message after a delay
                 let send_cancel = Task::delay_task(move || {
-                	let json_order = JsonOrder::params_to_json(order.clone());
+                	let json_order = serde_json::to_value(&order).expect("Couldn't serialize order");
                 	let json_send_task = tcp_json::tcp_send_json(json_order, addr.clone()).task;
                     tokio::spawn(json_send_task);
                 }, 1000).task;
@@ -80,9 +78,8 @@ impl RandBehavior {
 
 	            // Send them over JSON
 	            for order in &orders {
-	            	let addr = address.clone();
-	                // Don't want a full clone of the order, just params to make json
-	                let json_order = JsonOrder::order_to_json(order);
+	            	let addr = address;
+	                let json_order = serde_json::to_value(order).expect("Couldn't serialize order");
 	                // Spawn the task to send json over tcp
 	                let _h = thread::spawn(move || {
 	                	ws_json::ws_send_json(json_order, addr);
@@ -101,9 +98,9 @@ impl RandBehavior {
             let update_orders = trader_behavior::gen_rand_updates(Arc::clone(&traders), rng_upper);
             println!("updating {} traders", update_orders.len());
             for order in update_orders {
-            	let addr = address.clone();
+            	let addr = address;
 
-            	let json_order = JsonOrder::params_to_json(order);
+            	let json_order = serde_json::to_value(&order).expect("Couldn't serialize order");
                 
                 let _h = thread::spawn(move || {
                 	ws_json::ws_send_json(json_order, addr);
@@ -120,12 +117,11 @@ impl RandBehavior {
             let cancel_orders = trader_behavior::gen_rand_cancels(Arc::clone(&traders), rng_upper);
             println!("cancelling {} traders", cancel_orders.len());
             for order in cancel_orders {
-                println!("time: {:?}, cancelling: {:?} ", get_time(), order.0);
-                let addr = address.clone();
+                println!("time: {:?}, cancelling: {:?} ", get_time(), order.trader_id);
+                let addr = address;
                 // Send a cancel message after a delay
                 let send_cancel = Task::delay_task(move || {
-                	let addr = addr.clone();
-                	let json_order = JsonOrder::params_to_json(order.clone());
+                	let json_order = serde_json::to_value(&order).expect("Couldn't serialize order");
                 	let _h = thread::spawn(move || {
                 		ws_json::ws_send_json(json_order, addr);
                 	});
@@ -12,6 +12,12 @@ pub struct Traders {
 	pub traders: Mutex<HashMap<String, Order>>,
 }
 
+impl Default for Traders {
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
 impl Traders {
 	pub fn new() -> Self {
 		Traders {
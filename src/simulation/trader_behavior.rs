@@ -1,5 +1,6 @@
 use crate::simulation::trader::Traders;
-use crate::order::{Order, OrderType, TradeType, p_wise_sup, p_wise_dem};
+use crate::order::{Order, OrderType, TradeType};
+use crate::order::schedule::ScheduleSpec;
 
 use std::iter;
 use std::sync::Arc;
@@ -7,31 +8,18 @@ use rand::{Rng, thread_rng};
 use rand::distributions::Alphanumeric;
 
 
-/// Function for parsing an order into it's Json components. Workaround since
-/// Box<Fn(f64) -> f64 + Send + Sync + 'static cannot implement clone trait
-pub fn params_for_json(order: &Order) -> (String, OrderType, TradeType, f64, f64, f64) {
-    return (order.trader_id.clone(),
-        order.order_type.clone(),
-        order.trade_type.clone(),
-        order.p_low.clone(),
-        order.p_high.clone(),
-        order.u_max.clone());
-}
-
 /// A function to randomly generate update orders for existing traders within 
-/// the Trader HashMap. The output is a vector of tuples where each tuple contains
-/// the required parameters to generate a JSON formatted order. The supplied u32
-/// 'upper' is to change the probability with which an update will occur for a 
-/// given trader. Probability of update = (1 / upper), where upper > 0
-pub fn gen_rand_updates(t_struct: Arc<Traders>, upper: u32) 
--> Vec<(String, OrderType, TradeType, f64, f64, f64)> 
-{
+/// the Trader HashMap. The output is a vector of the update orders to send to
+/// the exchange. The supplied u32 'upper' is to change the probability with which 
+/// an update will occur for a given trader. Probability of update = (1 / upper), 
+/// where upper > 0
+pub fn gen_rand_updates(t_struct: Arc<Traders>, upper: u32) -> Vec<Order> {
 		let mut rng = thread_rng();
 		// Get a lock on the HashMap 
 		let mut orders = t_struct.traders.lock().unwrap();
 
-		// Vector of tuples to construct JSON messages
-		let mut to_send: Vec<_> = Vec::new();
+		// Vector of orders to send as JSON messages
+		let mut to_send: Vec<Order> = Vec::new();
 
 		// Iterate through hashmap and update based on rng
 		for order in orders.values_mut() {
@@ -39,8 +27,8 @@ pub fn gen_rand_updates(t_struct: Arc<Traders>, upper: u32)
 			if rng.gen_range(0, upper) == 1 {
 				// generate a new order with same trader_id and trader_type
 				let new_order = rand_update_order(order);
-				// parse and save the new order for params to make JSON
-				to_send.push(params_for_json(order));
+				// save a copy of the new order to send as JSON
+				to_send.push(new_order.clone());
 				// save the new order in the hashmap
 				*order = new_order;
 			}
@@ -49,19 +37,17 @@ pub fn gen_rand_updates(t_struct: Arc<Traders>, upper: u32)
 	}
 
 /// A function to randomly generate cancel orders for existing traders within 
-/// the Trader HashMap. The output is a vector of tuples where each tuple contains
-/// the required parameters to generate a JSON formatted order. The supplied u32
-/// 'upper' is to change the probability with which an update will occur for a 
-/// given trader. Probability of update = (1 / upper), where upper > 0
-pub fn gen_rand_cancels(t_struct: Arc<Traders>, upper: u32) 
--> Vec<(String, OrderType, TradeType, f64, f64, f64)> 
-{
+/// the Trader HashMap. The output is a vector of the cancel orders to send to
+/// the exchange. The supplied u32 'upper' is to change the probability with which 
+/// a cancel will occur for a given trader. Probability of cancel = (1 / upper), 
+/// where upper > 0
+pub fn gen_rand_cancels(t_struct: Arc<Traders>, upper: u32) -> Vec<Order> {
 		let mut rng = thread_rng();
 		// Get a lock on the HashMap 
 		let mut orders = t_struct.traders.lock().unwrap();
 
-		// Vector of tuples to construct JSON messages
-		let mut to_send: Vec<_> = Vec::new();
+		// Vector of orders to send as JSON messages
+		let mut to_send: Vec<Order> = Vec::new();
 
 		let length_before = orders.len();
 
@@ -70,15 +56,15 @@ pub fn gen_rand_cancels(t_struct: Arc<Traders>, upper: u32)
 			let rand = rng.gen_range(0, upper);
 			// order was randomly selected to be cancelled
 			if rand == 1 {
-				// copy order's params for cancel json
-				let mut p = params_for_json(order);
+				// copy the order for the cancel json
+				let mut cancel = order.clone();
 				// update OrderType to be a cancel order
-				p.1 = OrderType::Cancel;
-				to_send.push(p)
+				cancel.order_type = OrderType::Cancel;
+				to_send.push(cancel)
 			}
 
 			// (1 / upper) chance of cancelling the given order
-			!(rand == 1)
+			rand != 1
 		});
 
 		assert_eq!(length_before, orders.len() + to_send.len());
@@ -112,7 +98,7 @@ pub fn rand_ask_enter() -> Order {
 		p_l,
 		p_h,
 		u_max,
-		p_wise_sup(p_l, p_h, u_max)
+		ScheduleSpec::PiecewiseLinear,
 	)
 }
 
@@ -127,7 +113,7 @@ pub fn rand_bid_enter() -> Order {
 		p_l,
 		p_h,
 		u_max,
-		p_wise_dem(p_l, p_h, u_max),
+		ScheduleSpec::PiecewiseLinear,
 	)
 }
 
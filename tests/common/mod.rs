@@ -1,8 +1,12 @@
+#![allow(dead_code)]
+
 extern crate flow_rs;
 use flow_rs::exchange::order_processing::OrderProcessor;
+use flow_rs::exchange::queue_processing::QueueProcessor;
 use flow_rs::simulation::trader_behavior::*;
 use flow_rs::exchange::queue::*;
 use flow_rs::order::*;
+use flow_rs::order::schedule::ScheduleSpec;
 use flow_rs::exchange::order_book::*;
 use std::sync::Arc;
 use rand::Rng;
@@ -21,7 +25,7 @@ pub fn setup_bid_order() -> Order {
 		0.0,
 		100.0,
 		500.0,
-		poly_clos_from_coef(vec![-3.0, 4.0]),
+		ScheduleSpec::Polynomial { coefs: vec![-3.0, 4.0] },
 	)
 }
 
@@ -33,7 +37,7 @@ pub fn setup_ask_order() -> Order {
 		0.0,
 		100.0,
 		500.0,
-		poly_clos_from_coef(vec![-3.0, 4.0]),
+		ScheduleSpec::Polynomial { coefs: vec![-3.0, 4.0] },
 	)
 }
 
@@ -115,7 +119,7 @@ pub fn setup_orders() -> (Vec<Order>, Vec<Order>) {
     		i as f64, 
     		100.0, 
     		500.0,
-    		p_wise_dem(i as f64, 100.0, 500.0),
+    		ScheduleSpec::PiecewiseLinear,
 		));
 		asks.push(Order::new(
 			gen_order_id(), 
@@ -124,7 +128,7 @@ pub fn setup_orders() -> (Vec<Order>, Vec<Order>) {
     		i as f64, 
     		100.0, 
     		500.0,
-    		p_wise_sup(i as f64, 100.0, 500.0),
+    		ScheduleSpec::PiecewiseLinear,
 		));
 
 	}
@@ -132,13 +136,24 @@ pub fn setup_orders() -> (Vec<Order>, Vec<Order>) {
 	(bids, asks)
 }
 
+/// Sends the orders from setup_orders() through a queue and processes them
+/// into a fresh pair of bids and asks books.
+pub fn setup_full_books() -> (Arc<Book>, Arc<Book>) {
+	let queue = Arc::new(setup_queue());
+	let bids_book = Arc::new(setup_bids_book());
+	let asks_book = Arc::new(setup_asks_book());
 
+	let (bids, asks) = setup_orders();
+	for order in bids.into_iter().chain(asks) {
+		OrderProcessor::conc_recv_order(order, Arc::clone(&queue)).join().unwrap();
+	}
 
+	let handles = QueueProcessor::conc_process_order_queue(Arc::clone(&queue), 
+							Arc::clone(&bids_book),
+							Arc::clone(&asks_book));
+	for h in handles {
+		h.join().unwrap();
+	}
 
-
-
-
-
-
-
-
+	(bids_book, asks_book)
+}
@@ -1,9 +1,15 @@
 // extern crate <name_of_my_crate_to_test>
+#![allow(clippy::useless_format, clippy::redundant_pattern_matching)]
 use flow_rs::exchange::queue_processing::QueueProcessor;
-use flow_rs::exchange::order_processing::OrderProcessor;
-use flow_rs::exchange::order_processing::*;
+use flow_rs::exchange::order_processing::{OrderProcessor, JsonOrder};
 use flow_rs::order::*;
-use flow_rs::exchange::auction::Auction;
+use flow_rs::order::schedule::{Schedule, ScheduleSpec};
+use flow_rs::exchange::auction::{Auction, ClearingMethod, ClearingOutcome, TieBreak, CurveOptions, CurveSampling};
+use flow_rs::exchange::config::ExchangeConfig;
+use flow_rs::exchange::validation::RejectReason;
+use flow_rs::exchange::queue::Queue;
+use flow_rs::exchange::self_trade::{self, SelfTradeGuard, SelfTradePrevention, PreventionAction};
+use flow_rs::exchange::events::{EventQueue, ExchangeEvent};
 use std::sync::Arc;
 
 // Include the common module for setting up state for tests
@@ -23,11 +29,11 @@ fn test_add_order_to_book() {
 
 	let book = common::setup_bids_book();
 
-	book.add_order(bid);
+	book.add_order(bid).unwrap();
 
 	assert_eq!(book.len(), 1);
 
-	let order = book.orders.lock().unwrap().pop().unwrap();
+	let order = book.orders.lock().unwrap().iter().next().cloned().unwrap();
 
 	// The default closure: -3x + 4
 	assert_eq!(order.calculate(5.0), -11.0);
@@ -109,7 +115,7 @@ fn test_process_queue() {
 	assert_eq!(a_max_price, 100.0);
 	assert_eq!(a_min_price, 0.0);
 
-	let (min, max) = Auction::get_price_bounds(Arc::clone(&bids_book), Arc::clone(&asks_book));
+	let (min, max) = Auction::get_price_bounds(&bids_book.snapshot(), &asks_book.snapshot());
 	assert_eq!(min, 0.0);
 	assert_eq!(max, 100.0);
 
@@ -150,7 +156,7 @@ pub fn test_find_crossing_price() {
 	assert_eq!(bids_book.len(), 100);
 	assert_eq!(asks_book.len(), 100);
 
-	let cross_price = Auction::bs_cross(Arc::clone(&bids_book), Arc::clone(&asks_book)).unwrap();
+	let cross_price = Auction::bs_cross(&bids_book.snapshot(), &asks_book.snapshot()).price().unwrap();
 	assert!(Auction::equal_e(&cross_price, &81.09048166081236));
 }
 
@@ -213,12 +219,8 @@ pub fn test_update_order() {
 	assert_eq!(bids_book.len(), 100);
 	assert_eq!(asks_book.len(), 100);
 
-	// Find the order with id "jason"
-	let index = bids_book.peek_id_pos(format!("jason"));
-
-	// Unwrap the index and check order has been updating
-	if let Some(i) = index {
-		let order = &bids_book.orders.lock().unwrap()[i];
+	// Find the order with id "jason" and check order has been updating
+	if let Some(order) = bids_book.peek_trader_order("jason") {
 		assert_eq!(order.trader_id, format!("jason"));
 		assert_eq!(order.p_low, 99.9);
 		assert_eq!(order.p_high, 555.5);
@@ -294,10 +296,7 @@ pub fn test_cancel_order() {
 	assert_eq!(asks_book.len(), 100);
 
 	// Find the order with id "jason"
-	let index = bids_book.peek_id_pos(format!("jason"));
-
-	// Unwrap the index and check order has been updating
-	if let Some(_) = index {
+	if let Some(_) = bids_book.peek_trader_order("jason") {
 		panic!("Cancel Order should not exist anymore");
 	} 
 
@@ -307,24 +306,681 @@ pub fn test_cancel_order() {
 	// New min price will be 1.0 since orders iterated from p_lows 0..100 and we mutated the 0th order
 	assert_ne!(bids_book.get_min_price(), -1.0);
 	assert_eq!(bids_book.get_min_price(), 1.0);
+
+	// The books are still consistent after the mix of enters, updates and cancels
+	assert!(QueueProcessor::audit_books(&bids_book, &asks_book));
 }
 
+#[test]
+pub fn test_auction_fills() {
+	let (bids_book, asks_book) = common::setup_full_books();
+
+	let config = ExchangeConfig { clearing_method: ClearingMethod::BinarySearch, ..ExchangeConfig::default() };
+	let result = Auction::run_auction(&bids_book.snapshot(), &asks_book.snapshot(), &config, None);
+	let p = result.clearing_price.unwrap();
+	assert!(Auction::equal_e(&p, &81.09048166081236));
+
+	// Every order in both books receives a fill
+	assert_eq!(result.bid_fills.len(), 100);
+	assert_eq!(result.ask_fills.len(), 100);
+
+	// Fills are each order's schedule at p* and sum to the aggregates
+	let (dem, sup) = Auction::calc_aggs(p, &bids_book.snapshot(), &asks_book.snapshot());
+	let bid_total: f64 = result.bid_fills.iter().map(|f| f.rate).sum();
+	let ask_total: f64 = result.ask_fills.iter().map(|f| f.rate).sum();
+	assert!(Auction::equal_e(&bid_total, &dem));
+	assert!(Auction::equal_e(&ask_total, &sup));
+	assert!(Auction::equal_e(&result.volume, &dem.min(sup)));
+	assert!(result.imbalance.abs() < 0.000_001);
+
+	// No fill is negative or exceeds its order's u_max
+	for fill in result.bid_fills.iter().chain(result.ask_fills.iter()) {
+		assert!(fill.rate >= 0.0 && fill.rate <= 500.0);
+	}
+}
 
+#[test]
+pub fn test_auction_empty_books() {
+	let bids_book = Arc::new(common::setup_bids_book());
+	let asks_book = Arc::new(common::setup_asks_book());
 
+	let result = Auction::run_auction(&bids_book.snapshot(), &asks_book.snapshot(), &ExchangeConfig::default(), None);
+	assert_eq!(result.clearing_price, None);
+	assert!(result.outcome.is_no_trade());
+	assert!(result.bid_fills.is_empty());
+	assert_eq!(result.volume, 0.0);
+}
 
+#[test]
+pub fn test_json_order_quantity() {
+	let queue = Arc::new(common::setup_queue());
 
+	let msg = serde_json::json!({
+		"trader_id": "finite",
+		"order_type": "enter",
+		"trade_type": "bid",
+		"p_low": 10.0,
+		"p_high": 20.0,
+		"u_max": 5.0,
+		"quantity": 50.0,
+	});
+	JsonOrder::process_new(msg, Arc::clone(&queue), &ExchangeConfig::default(), None).unwrap();
 
+	let order = queue.pop().unwrap();
+	assert_eq!(order.quantity, Some(50.0));
+	assert_eq!(order.remaining, Some(50.0));
+
+	// Orders serialize directly and round trip
+	let json = serde_json::to_value(&order).unwrap();
+	assert_eq!(json["quantity"], 50.0);
+	assert_eq!(json["schedule"]["type"], "piecewise_linear");
+	let round_trip: Order = serde_json::from_value(json.clone()).unwrap();
+	assert_eq!(round_trip, order);
+
+	// And a serialized order is accepted back by the exchange
+	JsonOrder::process_new(json, Arc::clone(&queue), &ExchangeConfig::default(), None).unwrap();
+	assert_eq!(queue.pop().unwrap().quantity, Some(50.0));
+}
 
+#[test]
+pub fn test_json_order_schedule() {
+	let queue = Arc::new(common::setup_queue());
+	let config = ExchangeConfig::default();
+
+	let msg = serde_json::json!({
+		"trader_id": "points",
+		"order_type": "enter",
+		"trade_type": "bid",
+		"p_low": 0.0,
+		"p_high": 10.0,
+		"u_max": 10.0,
+		"schedule": {"type": "breakpoints", "points": [[0.0, 10.0], [5.0, 8.0], [10.0, 0.0]]},
+	});
+	JsonOrder::process_new(msg, Arc::clone(&queue), &config, None).unwrap();
+	let order = queue.pop().unwrap();
+	assert_eq!(order.calculate(2.5), 9.0);
+
+	// A schedule with unsorted points can't be evaluated and is never queued
+	let msg = serde_json::json!({
+		"trader_id": "unsorted",
+		"order_type": "enter",
+		"trade_type": "ask",
+		"p_low": 0.0,
+		"p_high": 10.0,
+		"u_max": 10.0,
+		"schedule": {"type": "breakpoints", "points": [[10.0, 10.0], [0.0, 0.0]]},
+	});
+	let rejected = JsonOrder::process_new(msg, Arc::clone(&queue), &config, None);
+	assert_eq!(rejected, Err(RejectReason::InvalidSchedule("schedule points must be sorted by price")));
+	assert_eq!(queue.len(), 0);
+
+	// A polynomial bid that demands more as the price rises is rejected
+	let msg = serde_json::json!({
+		"trader_id": "increasing",
+		"order_type": "enter",
+		"trade_type": "bid",
+		"p_low": 0.0,
+		"p_high": 10.0,
+		"u_max": 10.0,
+		"schedule": {"type": "polynomial", "coefs": [1.0, 0.0]},
+	});
+	let rejected = JsonOrder::process_new(msg, Arc::clone(&queue), &config, None);
+	assert_eq!(rejected, Err(RejectReason::InvalidShape("bid schedule increases with price")));
+	assert_eq!(queue.len(), 0);
+}
 
+#[test]
+pub fn test_reject_invalid_orders() {
+	let queue = Arc::new(common::setup_queue());
+	let config = ExchangeConfig::default();
+	let order = |trader_id: &str, p_low: f64, p_high: f64, u_max: f64| serde_json::json!({
+		"trader_id": trader_id,
+		"order_type": "enter",
+		"trade_type": "bid",
+		"p_low": p_low,
+		"p_high": p_high,
+		"u_max": u_max,
+	});
+
+	let reject = |msg| JsonOrder::process_new(msg, Arc::clone(&queue), &config, None).unwrap_err();
+	assert_eq!(reject(order("", 1.0, 2.0, 3.0)), RejectReason::EmptyTraderId);
+	assert_eq!(reject(order("trader", 2.0, 1.0, 3.0)), RejectReason::InvertedPrices);
+	assert_eq!(reject(order("trader", 1.0, 2.0, -3.0)), RejectReason::NegativeRate);
+	assert_eq!(reject(serde_json::json!({"trader_id": "trader"})), RejectReason::MalformedJson);
+
+	let mut bad_type = order("trader", 1.0, 2.0, 3.0);
+	bad_type["trade_type"] = serde_json::json!("swap");
+	assert_eq!(reject(bad_type), RejectReason::InvalidTradeType);
+
+	// A quantity that isn't positive is invalid even without a lot size
+	let mut no_quantity = order("trader", 1.0, 2.0, 3.0);
+	no_quantity["quantity"] = serde_json::json!(0.0);
+	assert_eq!(reject(no_quantity), RejectReason::InvalidQuantity);
+	assert_eq!(queue.len(), 0);
+}
 
+#[test]
+pub fn test_multiple_orders_per_trader() {
+	let queue = Arc::new(common::setup_queue());
+	let bids_book = Arc::new(common::setup_bids_book());
+	let asks_book = Arc::new(common::setup_asks_book());
+	let config = ExchangeConfig::default();
+	let order = |order_type: &str, p_low: f64, p_high: f64| serde_json::json!({
+		"trader_id": "market_maker",
+		"order_type": order_type,
+		"trade_type": "bid",
+		"p_low": p_low,
+		"p_high": p_high,
+		"u_max": 10.0,
+		"client_order_id": format!("mm-{}", p_low),
+	});
+	let process = |queue: &Arc<Queue>| {
+		for h in QueueProcessor::conc_process_order_queue(Arc::clone(queue), Arc::clone(&bids_book), Arc::clone(&asks_book)) {
+			h.join().unwrap();
+		}
+	};
+
+	// Two orders from one trader are both acknowledged with their own ids
+	let low = JsonOrder::process_new(order("enter", 10.0, 20.0), Arc::clone(&queue), &config, None).unwrap();
+	let high = JsonOrder::process_new(order("enter", 30.0, 40.0), Arc::clone(&queue), &config, None).unwrap();
+	assert_eq!(low.client_order_id, Some(String::from("mm-10")));
+	assert_ne!(low.order_id, high.order_id);
+	process(&queue);
+	assert_eq!(bids_book.trader_orders("market_maker").len(), 2);
+
+	// Updates target the order_id and keep it
+	let mut update = order("update", 50.0, 60.0);
+	update["order_id"] = serde_json::json!(high.order_id);
+	JsonOrder::process_new(update, Arc::clone(&queue), &config, None).unwrap();
+	process(&queue);
+	assert_eq!(bids_book.peek_order(high.order_id.unwrap()).unwrap().p_low, 50.0);
+
+	// Without an order_id the trader's orders are ambiguous and nothing is cancelled
+	JsonOrder::process_new(order("cancel", 10.0, 20.0), Arc::clone(&queue), &config, None).unwrap();
+	process(&queue);
+	assert_eq!(bids_book.len(), 2);
+
+	let mut cancel = order("cancel", 10.0, 20.0);
+	cancel["order_id"] = serde_json::json!(low.order_id);
+	JsonOrder::process_new(cancel, Arc::clone(&queue), &config, None).unwrap();
+	process(&queue);
+	assert_eq!(bids_book.trader_orders("market_maker"), vec![high.order_id.unwrap()]);
+}
 
+#[test]
+pub fn test_order_sequencing() {
+	let queue = Arc::new(common::setup_queue());
+	let bids_book = Arc::new(common::setup_bids_book());
+	let asks_book = Arc::new(common::setup_asks_book());
+	let config = ExchangeConfig::default();
+	let order = |trader_id: &str, trade_type: &str| serde_json::json!({
+		"trader_id": trader_id,
+		"order_type": "enter",
+		"trade_type": trade_type,
+		"p_low": 0.0,
+		"p_high": 100.0,
+		"u_max": 10.0,
+	});
+
+	let start = flow_rs::utility::get_time();
+	let bid = JsonOrder::process_new(order("buyer", "bid"), Arc::clone(&queue), &config, None).unwrap();
+	let ask = JsonOrder::process_new(order("seller", "ask"), Arc::clone(&queue), &config, None).unwrap();
+	assert!(bid.seq.unwrap() < ask.seq.unwrap());
+	assert!(bid.received.unwrap() >= start);
+	assert!(ask.received.unwrap() >= bid.received.unwrap());
+
+	for h in QueueProcessor::conc_process_order_queue(Arc::clone(&queue), Arc::clone(&bids_book), Arc::clone(&asks_book)) {
+		h.join().unwrap();
+	}
 
+	// The stamps survive the Book and are reported on the fills
+	let result = Auction::run_auction(&bids_book.snapshot(), &asks_book.snapshot(), &config, None);
+	assert_eq!(result.bid_fills[0].seq, bid.seq);
+	assert_eq!(result.bid_fills[0].received, bid.received);
+	assert_eq!(result.ask_fills[0].seq, ask.seq);
+}
 
+#[test]
+pub fn test_exact_crossing_price() {
+	let (bids_book, asks_book) = common::setup_full_books();
 
+	let exact = Auction::exact_cross(&bids_book.snapshot(), &asks_book.snapshot()).price().unwrap();
+	let searched = Auction::bs_cross(&bids_book.snapshot(), &asks_book.snapshot()).price().unwrap();
+	assert!(Auction::equal_e(&exact, &searched));
 
+	// Aggregate supply and demand are equal at the exact price
+	let (dem, sup) = Auction::calc_aggs(exact, &bids_book.snapshot(), &asks_book.snapshot());
+	assert!(Auction::equal_e(&dem, &sup));
 
+	// The exact price is identical across runs
+	let again = Auction::exact_cross(&bids_book.snapshot(), &asks_book.snapshot()).price().unwrap();
+	assert_eq!(exact, again);
+}
 
+#[test]
+pub fn test_clear_step_and_polynomial_books() {
+	let clear = |bid: Order, ask: Order, clearing_method: ClearingMethod| {
+		let bids_book = Arc::new(common::setup_bids_book());
+		let asks_book = Arc::new(common::setup_asks_book());
+		bids_book.add_order(bid).unwrap();
+		asks_book.add_order(ask).unwrap();
+		let config = ExchangeConfig { clearing_method, ..ExchangeConfig::default() };
+		Auction::run_auction(&bids_book.snapshot(), &asks_book.snapshot(), &config, None)
+	};
+	let linear_ask = || Order::new(String::from("ask"), OrderType::Enter, TradeType::Ask,
+		0.0, 10.0, 10.0, ScheduleSpec::PiecewiseLinear);
+
+	// Demand steps down from 4 to 2 at 5.0 while supply rises as p, so they cross at 4.0
+	let step_bid = || Order::new(String::from("bid"), OrderType::Enter, TradeType::Bid,
+		0.0, 10.0, 4.0, ScheduleSpec::Step { steps: vec![(0.0, 4.0), (5.0, 2.0), (8.0, 0.0)] });
+	for method in [ClearingMethod::BinarySearch, ClearingMethod::Exact] {
+		let result = clear(step_bid(), linear_ask(), method);
+		assert!((result.clearing_price.unwrap() - 4.0).abs() < 1e-6, "{:?}", result.outcome);
+		assert!((result.demand - result.supply).abs() < 1e-6);
+	}
 
+	// Supply of p^2 against demand of 100 - 10p crosses at 5 * (sqrt(5) - 1)
+	let linear_bid = || Order::new(String::from("bid"), OrderType::Enter, TradeType::Bid,
+		0.0, 10.0, 100.0, ScheduleSpec::PiecewiseLinear);
+	let squared_ask = || Order::new(String::from("ask"), OrderType::Enter, TradeType::Ask,
+		0.0, 10.0, 100.0, ScheduleSpec::Polynomial { coefs: vec![1.0, 0.0, 0.0] });
+	for method in [ClearingMethod::BinarySearch, ClearingMethod::Exact] {
+		let result = clear(linear_bid(), squared_ask(), method);
+		let expected = 5.0 * (5.0f64.sqrt() - 1.0);
+		assert!((result.clearing_price.unwrap() - expected).abs() < 1e-6, "{:?}", result.outcome);
+		assert!((result.demand - result.supply).abs() < 1e-6);
+	}
+}
 
+#[test]
+pub fn test_exact_cross_empty_books() {
+	let bids_book = Arc::new(common::setup_bids_book());
+	let asks_book = Arc::new(common::setup_asks_book());
+	assert_eq!(Auction::exact_cross(&bids_book.snapshot(), &asks_book.snapshot()), ClearingOutcome::EmptyBids { num_asks: 0 });
+}
 
+#[test]
+pub fn test_clearing_outcomes() {
+	let new_order = |tt: TradeType, p_low: f64, p_high: f64| {
+		Order::new(String::from("id"), OrderType::Enter, tt, p_low, p_high, 10.0, ScheduleSpec::PiecewiseLinear)
+	};
 
+	// Only asks
+	let bids_book = Arc::new(common::setup_bids_book());
+	let asks_book = Arc::new(common::setup_asks_book());
+	asks_book.add_order(new_order(TradeType::Ask, 50.0, 60.0)).unwrap();
+	for outcome in [Auction::bs_cross(&bids_book.snapshot(), &asks_book.snapshot()),
+						Auction::exact_cross(&bids_book.snapshot(), &asks_book.snapshot())] {
+		assert_eq!(outcome, ClearingOutcome::EmptyBids { num_asks: 1 });
+		assert_eq!(outcome.kind(), "empty_bids");
+	}
+
+	// Bids that only want to pay below where asks are willing to sell
+	bids_book.add_order(new_order(TradeType::Bid, 10.0, 20.0)).unwrap();
+	let outcome = Auction::exact_cross(&bids_book.snapshot(), &asks_book.snapshot());
+	assert_eq!(outcome, ClearingOutcome::NoOverlap { best_bid: 20.0, best_ask: 50.0 });
+	assert_eq!(outcome.price(), None);
+	assert!(outcome.is_no_trade());
+
+	// Only bids
+	let empty_asks = Arc::new(common::setup_asks_book());
+	let outcome = Auction::bs_cross(&bids_book.snapshot(), &empty_asks.snapshot());
+	assert_eq!(outcome, ClearingOutcome::EmptyAsks { num_bids: 1 });
+
+	// A unique cross
+	let (bids_book, asks_book) = common::setup_full_books();
+	match Auction::bs_cross(&bids_book.snapshot(), &asks_book.snapshot()) {
+		ClearingOutcome::Cross { price, demand, supply, iterations } => {
+			assert!(Auction::equal_e(&price, &81.09048166081236));
+			assert!(Auction::equal_e(&demand, &supply));
+			assert!(iterations > 0 && iterations < 1000);
+		},
+		other => panic!("Expected a cross, got {:?}", other),
+	}
+}
+
+#[test]
+pub fn test_tie_break_rules() {
+	// Bids demand 10 below 60 and asks supply 10 above 50, so supply
+	// equals demand over the whole interval [50, 60]
+	let bids_book = Arc::new(common::setup_bids_book());
+	let asks_book = Arc::new(common::setup_asks_book());
+	bids_book.add_order(Order::new(String::from("bid"), OrderType::Enter, TradeType::Bid,
+		60.0, 70.0, 10.0, ScheduleSpec::PiecewiseLinear)).unwrap();
+	asks_book.add_order(Order::new(String::from("ask"), OrderType::Enter, TradeType::Ask,
+		40.0, 50.0, 10.0, ScheduleSpec::PiecewiseLinear)).unwrap();
+
+	// Both clearing methods find the same interval
+	for outcome in [Auction::exact_cross(&bids_book.snapshot(), &asks_book.snapshot()),
+					Auction::bs_cross(&bids_book.snapshot(), &asks_book.snapshot())] {
+		match outcome {
+			ClearingOutcome::Interval { low, high, volume, .. } => {
+				assert!(Auction::equal_e(&low, &50.0));
+				assert!(Auction::equal_e(&high, &60.0));
+				assert!(Auction::equal_e(&volume, &10.0));
+			},
+			other => panic!("Expected an interval, got {:?}", other),
+		}
+	}
+
+	let clear = |tie_break: TieBreak, previous: Option<f64>| {
+		let config = ExchangeConfig { tie_break, ..ExchangeConfig::default() };
+		let result = Auction::run_auction(&bids_book.snapshot(), &asks_book.snapshot(), &config, previous);
+		assert_eq!(result.tie_break, tie_break);
+		assert!(Auction::equal_e(&result.volume, &10.0));
+		result.clearing_price.unwrap()
+	};
+
+	assert!(Auction::equal_e(&clear(TieBreak::Midpoint, None), &55.0));
+	assert!(Auction::equal_e(&clear(TieBreak::Lowest, None), &50.0));
+	assert!(Auction::equal_e(&clear(TieBreak::Highest, None), &60.0));
+	assert!(Auction::equal_e(&clear(TieBreak::ClosestToPrevious, Some(58.0)), &58.0));
+	assert!(Auction::equal_e(&clear(TieBreak::ClosestToPrevious, Some(80.0)), &60.0));
+	assert!(Auction::equal_e(&clear(TieBreak::ClosestToPrevious, None), &55.0));
+	assert!(Auction::equal_e(&clear(TieBreak::MaxVolume, None), &55.0));
+}
+
+#[test]
+pub fn test_binary_search_step_breakpoint() {
+	// Demand steps down from 10 to 2 at 50 where supply is 5, so the search closes on
+	// the float just below 50 and 50 itself. The step's breakpoint clears with less
+	// excess, and both clearing methods pick it.
+	let bids_book = Arc::new(common::setup_bids_book());
+	let asks_book = Arc::new(common::setup_asks_book());
+	bids_book.add_order(Order::new(String::from("bid"), OrderType::Enter, TradeType::Bid,
+		40.0, 60.0, 10.0, ScheduleSpec::Step { steps: vec![(40.0, 10.0), (50.0, 2.0), (60.0, 0.0)] })).unwrap();
+	asks_book.add_order(Order::new(String::from("ask"), OrderType::Enter, TradeType::Ask,
+		40.0, 60.0, 10.0, ScheduleSpec::PiecewiseLinear)).unwrap();
+
+	let (bids, asks) = (bids_book.snapshot(), asks_book.snapshot());
+	assert_eq!(Auction::bs_cross(&bids, &asks).price(), Some(50.0));
+	assert_eq!(Auction::exact_cross(&bids, &asks).price(), Some(50.0));
+}
+
+#[test]
+pub fn test_max_volume_breakpoints() {
+	// Demand falls from 10 at 30 to 0 at 40 and supply rises from 0 at 20 to 10 at 30,
+	// so the most volume trades at the breakpoint 30 rather than the ends or midpoint
+	let bids_book = Arc::new(common::setup_bids_book());
+	let asks_book = Arc::new(common::setup_asks_book());
+	bids_book.add_order(Order::new(String::from("bid"), OrderType::Enter, TradeType::Bid,
+		30.0, 40.0, 10.0, ScheduleSpec::PiecewiseLinear)).unwrap();
+	asks_book.add_order(Order::new(String::from("ask"), OrderType::Enter, TradeType::Ask,
+		20.0, 30.0, 10.0, ScheduleSpec::PiecewiseLinear)).unwrap();
+
+	let price = Auction::break_tie(0.0, 100.0, TieBreak::MaxVolume, None, &bids_book.snapshot(), &asks_book.snapshot());
+	assert!(Auction::equal_e(&price, &30.0));
+}
+
+#[test]
+pub fn test_tick_and_lot_size() {
+	let queue = Arc::new(common::setup_queue());
+	let config = ExchangeConfig { tick_size: Some(0.5), lot_size: Some(10.0), ..ExchangeConfig::default() };
+
+	// Prices are rounded to ticks and quantity down to whole lots on entry
+	let msg = serde_json::json!({
+		"trader_id": "grid",
+		"order_type": "enter",
+		"trade_type": "ask",
+		"p_low": 10.2,
+		"p_high": 20.8,
+		"u_max": 5.0,
+		"quantity": 55.0,
+	});
+	JsonOrder::process_new(msg, Arc::clone(&queue), &config, None).unwrap();
+	let order = queue.pop().unwrap();
+	assert_eq!(order.p_low, 10.0);
+	assert_eq!(order.p_high, 21.0);
+	assert_eq!(order.quantity, Some(50.0));
+
+	// A quantity below one lot is refused
+	let msg = serde_json::json!({
+		"trader_id": "tiny",
+		"order_type": "enter",
+		"trade_type": "ask",
+		"p_low": 10.0,
+		"p_high": 20.0,
+		"u_max": 5.0,
+		"quantity": 5.0,
+	});
+	assert_eq!(JsonOrder::process_new(msg, Arc::clone(&queue), &config, None), Err(RejectReason::QuantityBelowLot));
+	assert!(queue.pop().is_none());
+
+	// The clearing price lands on the tick grid and fills are rationed so
+	// the rate bought equals the rate sold
+	let (bids_book, asks_book) = common::setup_full_books();
+	let config = ExchangeConfig { tick_size: Some(0.25), ..ExchangeConfig::default() };
+	let result = Auction::run_auction(&bids_book.snapshot(), &asks_book.snapshot(), &config, None);
+	let p = result.clearing_price.unwrap();
+	assert_eq!(p, 81.0);
+
+	let (dem, sup) = Auction::calc_aggs(p, &bids_book.snapshot(), &asks_book.snapshot());
+	assert!(dem > sup);
+	assert!(Auction::equal_e(&result.imbalance, &(dem - sup)));
+
+	let bought: f64 = result.bid_fills.iter().map(|f| f.rate).sum();
+	let sold: f64 = result.ask_fills.iter().map(|f| f.rate).sum();
+	assert!(Auction::equal_e(&bought, &sold));
+	assert!(Auction::equal_e(&result.volume, &sup));
+
+	// Each bid is rationed by the same proportion
+	let ratio = sup / dem;
+	for (fill, order) in result.bid_fills.iter().zip(bids_book.orders.lock().unwrap().iter()) {
+		assert!(Auction::equal_e(&fill.rate, &(order.rate(p) * ratio)));
+	}
+}
+
+#[test]
+pub fn test_limit_orders() {
+	let limit = |id: &str, tt: TradeType, price: f64, u_max: f64| {
+		Order::limit(String::from(id), OrderType::Enter, tt, price, u_max)
+	};
+	let piecewise = |id: &str, tt: TradeType, p_low: f64, p_high: f64, u_max: f64| {
+		Order::new(String::from(id), OrderType::Enter, tt, p_low, p_high, u_max, ScheduleSpec::PiecewiseLinear)
+	};
+
+	// Each case is the orders in the books, the clearing price and the volume traded
+	let cases = [
+		// Two limit bids against a smaller limit ask at the same price
+		(vec![limit("b1", TradeType::Bid, 50.0, 10.0), limit("b2", TradeType::Bid, 50.0, 10.0)],
+			vec![limit("a1", TradeType::Ask, 50.0, 5.0)], 50.0, 5.0),
+		// A limit ask that oversupplies a limit bid
+		(vec![limit("b1", TradeType::Bid, 50.0, 5.0)],
+			vec![limit("a1", TradeType::Ask, 50.0, 20.0)], 50.0, 5.0),
+		// A limit bid above where a piecewise ask is fully supplied
+		(vec![limit("b1", TradeType::Bid, 60.0, 15.0)],
+			vec![piecewise("a1", TradeType::Ask, 40.0, 60.0, 10.0)], 60.0, 10.0),
+		// A limit ask inside a piecewise bid's range
+		(vec![piecewise("b1", TradeType::Bid, 40.0, 60.0, 10.0)],
+			vec![limit("a1", TradeType::Ask, 50.0, 20.0)], 50.0, 5.0),
+	];
+
+	for method in [ClearingMethod::Exact, ClearingMethod::BinarySearch] {
+		for (bids, asks, price, volume) in cases.iter() {
+			let bids_book = Arc::new(common::setup_bids_book());
+			let asks_book = Arc::new(common::setup_asks_book());
+			for (book, orders) in [(&bids_book, bids), (&asks_book, asks)] {
+				for order in orders.iter().cloned() {
+					book.add_order(order).unwrap();
+				}
+			}
+
+			let config = ExchangeConfig { clearing_method: method, ..ExchangeConfig::default() };
+			let result = Auction::run_auction(&bids_book.snapshot(), &asks_book.snapshot(), &config, None);
+			let p = result.clearing_price.unwrap_or_else(|| panic!("{:?} {:?}", method, result.outcome));
+			assert!(Auction::equal_e(&p, price), "{:?} cleared at {} not {}", method, p, price);
+			assert!(Auction::equal_e(&result.volume, volume));
+
+			// The long side is rationed at the limit price and no fill is NaN
+			let bought: f64 = result.bid_fills.iter().map(|f| f.rate).sum();
+			let sold: f64 = result.ask_fills.iter().map(|f| f.rate).sum();
+			assert!(Auction::equal_e(&bought, &sold));
+			assert!(result.bid_fills.iter().chain(result.ask_fills.iter()).all(|f| f.rate.is_finite()));
+		}
+	}
+}
+
+#[test]
+pub fn test_deadline_orders() {
+	let queue = Arc::new(common::setup_queue());
+	let config = ExchangeConfig::default();
+	let deadline = flow_rs::utility::get_time().as_millis() as u64 + 10_000;
+	let deadline_order = |quantity: Option<f64>, deadline: u64| serde_json::json!({
+		"trader_id": "desk",
+		"order_type": "enter",
+		"trade_type": "bid",
+		"p_low": 10.0,
+		"p_high": 20.0,
+		"u_max": 1.0,
+		"quantity": quantity,
+		"deadline": deadline,
+	});
+
+	// The exchange replaces u_max with the rate that trades 100 units in 10 seconds
+	JsonOrder::process_new(deadline_order(Some(100.0), deadline), Arc::clone(&queue), &config, None).unwrap();
+	let order = queue.pop().unwrap();
+	assert_eq!(order.deadline, Some(deadline));
+	assert!((order.u_max - 10.0).abs() < 0.1);
+
+	// Deadlines need a quantity to spread and can't already have passed
+	assert_eq!(JsonOrder::process_new(deadline_order(None, deadline), Arc::clone(&queue), &config, None),
+		Err(RejectReason::InvalidDeadline));
+	assert_eq!(JsonOrder::process_new(deadline_order(Some(100.0), 1000), Arc::clone(&queue), &config, None),
+		Err(RejectReason::InvalidDeadline));
+	assert!(queue.pop().is_none());
+}
+
+#[test]
+pub fn test_market_curves() {
+	let (bids_book, asks_book) = common::setup_full_books();
+
+	// An evenly spaced grid over the books' price range
+	let options = CurveOptions { sampling: CurveSampling::Grid { points: 11 }, range: None };
+	let curves = Auction::calc_curves(Arc::clone(&bids_book), Arc::clone(&asks_book), &options);
+	assert_eq!(curves.points.len(), 11);
+	assert_eq!(curves.bids_version, bids_book.version());
+	assert_eq!((curves.points[0].price, curves.points[10].price), (0.0, 100.0));
+	for point in curves.points.iter() {
+		let (dem, sup) = Auction::calc_aggs(point.price, &bids_book.snapshot(), &asks_book.snapshot());
+		assert_eq!((point.demand, point.supply), (dem, sup));
+	}
+
+	// Demand falls and supply rises with price
+	for pair in curves.points.windows(2) {
+		assert!(pair[1].demand <= pair[0].demand);
+		assert!(pair[1].supply >= pair[0].supply);
+	}
+
+	// Breakpoints inside a narrower range, sorted and including its ends
+	let options = CurveOptions { sampling: CurveSampling::Breakpoints, range: Some((20.5, 30.5)) };
+	let curves = Auction::calc_curves(Arc::clone(&bids_book), Arc::clone(&asks_book), &options);
+	let prices: Vec<f64> = curves.points.iter().map(|p| p.price).collect();
+	assert_eq!(prices.first(), Some(&20.5));
+	assert_eq!(prices.last(), Some(&30.5));
+	assert!(prices.windows(2).all(|w| w[0] < w[1]));
+
+	// The curves serialize for the protocol
+	let json = serde_json::to_value(&curves).unwrap();
+	assert_eq!(json["points"][0]["price"], 20.5);
+	let options: CurveOptions = serde_json::from_value(serde_json::json!({
+		"sampling": { "type": "grid", "points": 5 },
+		"range": null,
+	})).unwrap();
+	assert_eq!(options, CurveOptions { sampling: CurveSampling::Grid { points: 5 }, range: None });
+
+	// Empty books have no curves
+	let empty = Arc::new(common::setup_bids_book());
+	let curves = Auction::calc_curves(Arc::clone(&empty), Arc::new(common::setup_asks_book()), &CurveOptions::default());
+	assert!(curves.points.is_empty());
+}
+
+#[test]
+fn test_self_trade_prevention() {
+	let limit = |id: &str, tt: TradeType, u_max: f64, seq: u64| {
+		let mut order = Order::limit(String::from(id), OrderType::Enter, tt, 50.0, u_max);
+		order.seq = Some(seq);
+		order
+	};
+	// "both" bids first and then asks, against an outside seller
+	let setup = || {
+		let bids = Arc::new(common::setup_bids_book());
+		let asks = Arc::new(common::setup_asks_book());
+		bids.add_order(limit("both", TradeType::Bid, 10.0, 1)).unwrap();
+		asks.add_order(limit("both", TradeType::Ask, 4.0, 2)).unwrap();
+		asks.add_order(limit("seller", TradeType::Ask, 10.0, 3)).unwrap();
+		(bids, asks)
+	};
+
+	// Without prevention "both" buys from themselves
+	let (bids, asks) = setup();
+	let result = Auction::run_auction(&bids.snapshot(), &asks.snapshot(), &ExchangeConfig::default(), None);
+	assert!(Auction::equal_e(&result.volume, &10.0));
+	assert!(result.self_trade.is_empty());
+
+	// Netting takes the 4 sold from the 10 bought, leaving the seller to fill 6
+	let (bids, asks) = setup();
+	let config = ExchangeConfig { self_trade: SelfTradePrevention::Net, ..ExchangeConfig::default() };
+	let result = Auction::run_auction(&bids.snapshot(), &asks.snapshot(), &config, None);
+	assert!(Auction::equal_e(&result.volume, &6.0));
+	assert!(Auction::equal_e(&result.demand, &6.0));
+	assert!(Auction::equal_e(&result.supply, &10.0));
+	assert!(Auction::equal_e(&result.imbalance, &-4.0));
+	assert!(Auction::equal_e(&result.self_trade.volume, &4.0));
+	assert!(result.self_trade.prevented.iter().all(|t| t.trader_id == "both" && t.action == PreventionAction::Netted));
+	let rate = |fills: &[flow_rs::exchange::auction::Fill], id: &str| -> f64 {
+		fills.iter().filter(|f| f.trader_id == id).map(|f| f.rate).sum()
+	};
+	assert!(Auction::equal_e(&rate(&result.ask_fills, "both"), &0.0));
+	assert!(Auction::equal_e(&rate(&result.ask_fills, "seller"), &6.0));
+
+	// Through the gateway orders are checked on arrival instead
+	let arrive = |mode: SelfTradePrevention| {
+		let bids = Arc::new(common::setup_bids_book());
+		let asks = Arc::new(common::setup_asks_book());
+		let queue = Arc::new(common::setup_queue());
+		let events = Arc::new(EventQueue::new());
+		let guard = SelfTradeGuard::new(Arc::clone(&bids), Arc::clone(&asks), Arc::clone(&events));
+		let config = ExchangeConfig { self_trade: mode, ..ExchangeConfig::default() };
+		let process = || {
+			for h in QueueProcessor::conc_process_order_queue(Arc::clone(&queue), Arc::clone(&bids), Arc::clone(&asks)) {
+				h.join().unwrap();
+			}
+		};
+		let enter = |id: &str, tt: &str, u_max: f64| {
+			let msg = serde_json::json!({"trader_id": id, "order_type": "enter", "trade_type": tt,
+				"p_low": 50.0, "p_high": 50.0, "u_max": u_max});
+			let ack = JsonOrder::process_new(msg, Arc::clone(&queue), &config, Some(&guard));
+			process();
+			ack
+		};
+		let acks = vec![enter("both", "bid", 10.0), enter("both", "ask", 4.0), enter("seller", "ask", 10.0)];
+		let result = Auction::run_auction(&bids.snapshot(), &asks.snapshot(), &config, None);
+		let report = self_trade::report_removed(&guard.take(), &result);
+		(acks, result, report, events)
+	};
+
+	// Rejecting the second order refuses the ask, "both" buys 10 from the seller
+	let (acks, result, report, events) = arrive(SelfTradePrevention::RejectSecond);
+	assert_eq!(acks[1], Err(RejectReason::SelfTrade));
+	assert!(acks[0].is_ok() && acks[2].is_ok());
+	assert!(Auction::equal_e(&result.volume, &10.0));
+	assert!(Auction::equal_e(&report.volume, &4.0));
+	assert_eq!(report.prevented[0].action, PreventionAction::Rejected);
+	assert!(events.is_empty());
+
+	// Cancelling the older order removes the resting bid, leaving nothing to cross
+	let (acks, result, report, events) = arrive(SelfTradePrevention::CancelOlder);
+	assert!(acks.iter().all(|ack| ack.is_ok()));
+	assert_eq!(result.clearing_price, None);
+	assert_eq!((report.prevented[0].trade_type.clone(), report.prevented[0].action), (TradeType::Bid, PreventionAction::Cancelled));
+	let cancelled = events.pop_for("both");
+	assert_eq!(cancelled.len(), 1);
+	assert!(matches!(cancelled[0], ExchangeEvent::SelfTradePrevented { action: PreventionAction::Cancelled, .. }));
+
+	// Off and Net accept every order
+	for mode in [SelfTradePrevention::Off, SelfTradePrevention::Net] {
+		let (acks, _, report, _) = arrive(mode);
+		assert!(acks.iter().all(|ack| ack.is_ok()));
+		assert!(report.is_empty());
+	}
+}